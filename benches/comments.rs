@@ -0,0 +1,48 @@
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use reddit::client::parse_link_comments;
+use reddit::models::RedditComment;
+use reddit::models::RedditResponse;
+use reddit::models::RedditThing;
+
+fn count_tree(comments: &[RedditComment]) -> usize {
+    comments
+        .iter()
+        .map(|comment| {
+            let replies = match comment.replies.as_ref().map(|r| r.get()) {
+                Some(Ok(RedditResponse::Listing(listing))) => listing
+                    .children
+                    .iter()
+                    .filter_map(|thing| match thing {
+                        RedditThing::Comment(c) => Some(count_tree(std::slice::from_ref(c))),
+                        _ => None,
+                    })
+                    .sum(),
+                _ => 0,
+            };
+            1 + replies
+        })
+        .sum()
+}
+
+fn bench_comments(c: &mut Criterion) {
+    let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")
+        .expect("run benches from the crate root");
+
+    let mut group = c.benchmark_group("bapcsalescanada.post.json");
+    group.bench_function("serde_json::Value", |b| {
+        b.iter(|| serde_json::from_str::<serde_json::Value>(black_box(&text)).unwrap())
+    });
+    group.bench_function("top level only", |b| {
+        b.iter(|| parse_link_comments(black_box(&text)).unwrap())
+    });
+    group.bench_function("full tree", |b| {
+        b.iter(|| count_tree(&parse_link_comments(black_box(&text)).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_comments);
+criterion_main!(benches);
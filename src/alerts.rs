@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+
+use crate::client::fetch_info;
+use crate::deals::Deal;
+use crate::expiry::classify;
+use crate::expiry::DealStatus;
+use crate::models::RedditLink;
+use crate::models::RedditThing;
+use crate::watch::WatchEvent;
+
+/// Alert rules as written in a config file.
+///
+/// ```toml
+/// [[rule]]
+/// name = "cheap 9800X3D"
+/// categories = ["CPU"]
+/// keywords = ["9800x3d"]
+/// max_price = 650.0
+/// retailers_deny = ["aliexpress"]
+/// min_score = 10
+/// min_score_after_minutes = 30
+/// ```
+///
+/// Every condition a rule sets must hold for it to match. Text comparisons
+/// ignore case, and retailers also ignore spaces and punctuation, so
+/// `Best Buy` matches `bestbuy.ca`.
+#[derive(Deserialize, Debug, Default)]
+pub struct AlertRules {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    /// Deal `[TAG]`s, any of which matches.
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Words or phrases in the title, any of which matches.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub title_regex: Option<Regex>,
+    pub max_price: Option<f64>,
+    /// Only these retailers, if any are listed.
+    #[serde(default)]
+    pub retailers_allow: Vec<String>,
+    #[serde(default)]
+    pub retailers_deny: Vec<String>,
+    pub min_score: Option<i64>,
+    /// How old a post must be before `min_score` is checked. Younger posts wait.
+    #[serde(default)]
+    pub min_score_after_minutes: u64,
+    #[serde(default = "default_true")]
+    pub exclude_nsfw: bool,
+    /// Skip posts whose flair or title says the deal is over.
+    #[serde(default = "default_true")]
+    pub exclude_expired: bool,
+    /// Also skip posts with only some sign of the deal being over.
+    #[serde(default)]
+    pub exclude_suspect: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(de)?
+        .map(|pattern| Regex::new(&format!("(?i){pattern}")).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// A post that matched a rule.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    /// Why the rule matched, one entry per condition.
+    pub reasons: Vec<String>,
+    pub link_name: String,
+    pub subreddit: String,
+    pub author: String,
+    pub title: String,
+    /// Where the post links to, e.g. the retailer's product page.
+    pub url: String,
+    pub permalink: String,
+    pub score: i64,
+    pub created_utc: f64,
+    pub deal: Option<Deal>,
+}
+
+/// The result of checking one rule against one post.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleOutcome {
+    Matched(Vec<String>),
+    /// Everything else matched, but the post is too young to judge its score.
+    Pending,
+    Rejected,
+}
+
+impl AlertRules {
+    /// Load rules from a `.toml`, `.yaml` or `.yml` file.
+    pub async fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let text = tokio::fs::read_to_string(path).await?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => eyre::bail!(
+                "Expected a .toml or .yaml rules file, got {}",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> eyre::Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// YAML files hold the same structure, with `rule` as a list.
+    pub fn from_yaml(text: &str) -> eyre::Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+}
+
+impl AlertRule {
+    /// Check the rule against a post that is `age_secs` old.
+    pub fn evaluate(&self, link: &RedditLink, deal: Option<&Deal>, age_secs: f64) -> RuleOutcome {
+        let mut reasons = Vec::new();
+        if self.exclude_nsfw && link.over_18 {
+            return RuleOutcome::Rejected;
+        }
+        if self.exclude_expired || self.exclude_suspect {
+            // New posts have no comments yet, so this goes on flair and title
+            let status = classify(link, None).status;
+            if (self.exclude_expired && status == DealStatus::Expired)
+                || (self.exclude_suspect && status >= DealStatus::Suspect)
+            {
+                return RuleOutcome::Rejected;
+            }
+        }
+        if !self.categories.is_empty() {
+            let Some(category) = deal.and_then(|d| d.category.as_deref()) else {
+                return RuleOutcome::Rejected;
+            };
+            if !self
+                .categories
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(category))
+            {
+                return RuleOutcome::Rejected;
+            }
+            reasons.push(format!("category is {category}"));
+        }
+        if !self.keywords.is_empty() {
+            let title = link.title.to_lowercase();
+            let Some(keyword) = self
+                .keywords
+                .iter()
+                .find(|k| title.contains(&k.to_lowercase()))
+            else {
+                return RuleOutcome::Rejected;
+            };
+            reasons.push(format!("title mentions {keyword:?}"));
+        }
+        if let Some(regex) = &self.title_regex {
+            let Some(m) = regex.find(&link.title) else {
+                return RuleOutcome::Rejected;
+            };
+            reasons.push(format!("title matches {:?}", m.as_str()));
+        }
+        if let Some(max_price) = self.max_price {
+            match deal.and_then(|d| d.price) {
+                Some(price) if price <= max_price => {
+                    reasons.push(format!("price ${price:.2} is at most ${max_price:.2}"))
+                }
+                _ => return RuleOutcome::Rejected,
+            }
+        }
+        if !self.retailers_allow.is_empty() || !self.retailers_deny.is_empty() {
+            let retailer = deal
+                .and_then(|d| d.retailer.as_deref())
+                .unwrap_or(&link.domain);
+            let candidates = [retailer, link.domain.as_str()];
+            if self
+                .retailers_deny
+                .iter()
+                .any(|r| candidates.iter().any(|c| retailer_matches(r, c)))
+            {
+                return RuleOutcome::Rejected;
+            }
+            if !self.retailers_allow.is_empty() {
+                let Some(allowed) = self
+                    .retailers_allow
+                    .iter()
+                    .find(|r| candidates.iter().any(|c| retailer_matches(r, c)))
+                else {
+                    return RuleOutcome::Rejected;
+                };
+                reasons.push(format!("retailer is {allowed}"));
+            }
+        }
+        if let Some(min_score) = self.min_score {
+            let wait_secs = self.min_score_after_minutes as f64 * 60.0;
+            if link.score < min_score {
+                // The score may still climb until the post is old enough to judge
+                return if age_secs < wait_secs {
+                    RuleOutcome::Pending
+                } else {
+                    RuleOutcome::Rejected
+                };
+            }
+            reasons.push(format!("score {} is at least {min_score}", link.score));
+        }
+        RuleOutcome::Matched(reasons)
+    }
+}
+
+fn retailer_matches(pattern: &str, retailer: &str) -> bool {
+    let squash = |s: &str| {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    let pattern = squash(pattern);
+    !pattern.is_empty() && squash(retailer).contains(&pattern)
+}
+
+/// How long after a post was created the engine remembers it by default.
+pub const DEFAULT_ALERT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Evaluates rules against incoming posts, alerting at most once per rule and post.
+///
+/// Posts waiting on a score threshold are kept and re-checked by
+/// [`AlertEngine::recheck_pending`] until they match or age out.
+pub struct AlertEngine {
+    rules: AlertRules,
+    /// Rule and post fullname of each alert sent, with the post's `created_utc`.
+    fired: HashMap<(String, String), f64>,
+    /// Posts with at least one pending rule, keyed by fullname.
+    pending: BTreeMap<String, RedditLink>,
+    retention: Duration,
+}
+
+impl AlertEngine {
+    pub fn new(rules: AlertRules) -> Self {
+        Self {
+            rules,
+            fired: HashMap::new(),
+            pending: BTreeMap::new(),
+            retention: DEFAULT_ALERT_RETENTION,
+        }
+    }
+
+    /// Forget posts this long after they were created. Rules that wait longer
+    /// for a score keep their posts until the wait is over.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules.rules
+    }
+
+    /// Fullnames of posts that may still match once their score is known.
+    pub fn pending(&self) -> impl Iterator<Item = &str> {
+        self.pending.keys().map(String::as_str)
+    }
+
+    /// Check a post against every rule as of `now_utc`.
+    pub fn offer(&mut self, link: RedditLink, now_utc: f64) -> Vec<Alert> {
+        let deal = Deal::from_link(&link);
+        let age_secs = now_utc - link.created_utc;
+        let mut alerts = Vec::new();
+        let mut waiting = false;
+        for rule in &self.rules.rules {
+            let key = (rule.name.clone(), link.name.clone());
+            if self.fired.contains_key(&key) {
+                continue;
+            }
+            match rule.evaluate(&link, deal.as_ref(), age_secs) {
+                RuleOutcome::Matched(reasons) => {
+                    self.fired.insert(key, link.created_utc);
+                    alerts.push(Alert {
+                        rule: rule.name.clone(),
+                        reasons,
+                        link_name: link.name.clone(),
+                        subreddit: link.subreddit.clone(),
+                        author: link.author.clone(),
+                        title: link.title.clone(),
+                        url: link.url.clone(),
+                        permalink: link.permalink.clone(),
+                        score: link.score,
+                        created_utc: link.created_utc,
+                        deal: deal.clone(),
+                    });
+                }
+                RuleOutcome::Pending => waiting = true,
+                RuleOutcome::Rejected => {}
+            }
+        }
+        if waiting {
+            self.pending.insert(link.name.clone(), link);
+        } else {
+            self.pending.remove(&link.name);
+        }
+        alerts
+    }
+
+    /// Offer new posts from a [`crate::watch::Watcher`]; comments are ignored.
+    pub fn offer_event(&mut self, event: WatchEvent, now_utc: f64) -> Vec<Alert> {
+        match event {
+            WatchEvent::NewPost(link) => self.offer(*link, now_utc),
+            WatchEvent::NewComment(_) => vec![],
+        }
+    }
+
+    /// Forget posts created before the retention period, so a post that was
+    /// deleted, or never got a score, doesn't wait forever.
+    pub fn expire(&mut self, now_utc: f64) {
+        let longest_wait = self
+            .rules
+            .rules
+            .iter()
+            .filter(|rule| rule.min_score.is_some())
+            .map(|rule| rule.min_score_after_minutes as f64 * 60.0)
+            .fold(0.0, f64::max);
+        let horizon = now_utc - self.retention.as_secs_f64().max(longest_wait);
+        self.pending.retain(|_, link| link.created_utc >= horizon);
+        self.fired.retain(|_, created_utc| *created_utc >= horizon);
+    }
+
+    /// Re-fetch pending posts for their current score and offer them again,
+    /// after forgetting posts past the retention period.
+    pub async fn recheck_pending(
+        &mut self,
+        client: &reqwest::Client,
+        now_utc: f64,
+    ) -> eyre::Result<Vec<Alert>> {
+        self.expire(now_utc);
+        let names: Vec<String> = self.pending.keys().cloned().collect();
+        let mut alerts = Vec::new();
+        for thing in fetch_info(client, &names).await? {
+            if let RedditThing::Link(link) = thing {
+                alerts.extend(self.offer(link, now_utc));
+            }
+        }
+        Ok(alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn rules_from_toml_and_yaml() -> eyre::Result<()> {
+        let toml = AlertRules::from_toml(
+            r#"
+            [[rule]]
+            name = "cheap gpu"
+            categories = ["gpu"]
+            max_price = 300
+            retailers_deny = ["AliExpress"]
+
+            [[rule]]
+            name = "x3d"
+            title_regex = "9800\\s*x3d"
+            exclude_expired = false
+            "#,
+        )?;
+        let yaml = AlertRules::from_yaml(
+            "rule:\n  - name: cheap gpu\n    categories: [gpu]\n    max_price: 300\n",
+        )?;
+        assert_eq!(toml.rules.len(), 2);
+        assert_eq!(yaml.rules[0].max_price, Some(300.0));
+        assert!(toml.rules[0].exclude_expired);
+        assert!(AlertRules::from_toml("[[rule]]\nname = \"x\"\nmax_prize = 1").is_err());
+
+        let links = example_links()?;
+        let mut engine = AlertEngine::new(toml);
+        let now = links.iter().map(|l| l.created_utc).fold(0.0, f64::max) + 3600.0;
+        let alerts: Vec<Alert> = links
+            .into_iter()
+            .flat_map(|link| engine.offer(link, now))
+            .collect();
+
+        let gpu: Vec<&Alert> = alerts.iter().filter(|a| a.rule == "cheap gpu").collect();
+        assert_eq!(gpu.len(), 1);
+        assert!(gpu[0].title.contains("RX 6600"));
+        assert_eq!(
+            gpu[0].reasons,
+            vec!["category is GPU", "price $270.00 is at most $300.00"]
+        );
+
+        // Expired posts are only skipped when the rule says so
+        let x3d = alerts.iter().filter(|a| a.rule == "x3d").count();
+        assert!(x3d >= 5);
+        Ok(())
+    }
+
+    #[test]
+    fn score_thresholds_wait_for_age() -> eyre::Result<()> {
+        let rules = AlertRules::from_toml(
+            "[[rule]]\nname = \"popular\"\nmin_score = 1000\nmin_score_after_minutes = 30\n",
+        )?;
+        let mut engine = AlertEngine::new(rules);
+        let link = example_links()?.into_iter().nth(2).unwrap();
+        let created = link.created_utc;
+        let name = link.name.clone();
+
+        assert!(engine.offer(link, created + 60.0).is_empty());
+        assert_eq!(engine.pending().collect::<Vec<_>>(), vec![name.as_str()]);
+
+        let mut link = example_links()?.into_iter().nth(2).unwrap();
+        link.score = 1500;
+        assert_eq!(engine.offer(link, created + 600.0).len(), 1);
+        assert_eq!(engine.pending().count(), 0);
+
+        // Each rule alerts once per post
+        let link = example_links()?.into_iter().nth(2).unwrap();
+        assert!(engine.offer(link, created + 7200.0).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn old_posts_age_out() -> eyre::Result<()> {
+        let rules = AlertRules::from_toml(
+            "[[rule]]\nname = \"popular\"\nmin_score = 1000\nmin_score_after_minutes = 30\n\n\
+             [[rule]]\nname = \"any\"\nexclude_expired = false\n",
+        )?;
+        let mut engine = AlertEngine::new(rules).with_retention(Duration::from_secs(3600));
+        let link = example_links()?.into_iter().nth(2).unwrap();
+        let created = link.created_utc;
+
+        assert_eq!(engine.offer(link, created + 60.0).len(), 1);
+        assert_eq!(engine.pending().count(), 1);
+        engine.expire(created + 1800.0);
+        assert_eq!(engine.pending().count(), 1);
+
+        // A post that never comes back from a recheck is dropped, and so is its alert
+        engine.expire(created + 7200.0);
+        assert_eq!(engine.pending().count(), 0);
+        assert!(engine.fired.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+use std::future::Future;
+
+use crate::client::base_url;
+use crate::client::fetch_listing;
+use crate::client::SubredditSlug;
+use crate::models::RedditLink;
+use crate::models::RedditListing;
+use crate::models::RedditThing;
+
+/// Reddit stops paginating a listing after roughly this many items.
+pub const LISTING_LIMIT: usize = 1000;
+
+/// Listings that reach older posts once `new` runs out, each paged by its own cursor.
+const FALLBACK_LISTINGS: &[&str] = &[
+    "top.json?t=all",
+    "top.json?t=year",
+    "top.json?t=month",
+    "controversial.json?t=all",
+    "controversial.json?t=year",
+];
+
+/// What a backfill found, and whether that's known to be everything.
+#[derive(Debug)]
+pub struct BackfillReport {
+    /// Posts created at or after the cutoff, newest first.
+    pub links: Vec<RedditLink>,
+    /// Whether `new` reached the cutoff. If not, the older posts came from
+    /// listings in no time order and some are likely missing.
+    pub complete: bool,
+}
+
+/// Fetch every post in a subreddit created at or after `since_utc` (unix seconds).
+///
+/// Walks `/r/{sub}/new` until posts older than the cutoff appear. If Reddit's
+/// listing limit runs out first, older posts are gathered by walking the
+/// `top` and `controversial` listings too, which reach further back but in no
+/// time order, so the report is marked incomplete. Results are deduplicated
+/// by id.
+pub async fn backfill_subreddit(
+    client: &reqwest::Client,
+    subreddit: &SubredditSlug,
+    since_utc: f64,
+) -> eyre::Result<BackfillReport> {
+    let fetch = |url: String| async move { fetch_listing(client, &url).await };
+    backfill_with(&base_url(), subreddit, since_utc, fetch).await
+}
+
+async fn backfill_with<F, Fut>(
+    base_url: &str,
+    subreddit: &SubredditSlug,
+    since_utc: f64,
+    fetch: F,
+) -> eyre::Result<BackfillReport>
+where
+    F: Fn(String) -> Fut + Copy,
+    Fut: Future<Output = eyre::Result<RedditListing>>,
+{
+    let mut collected = Collected::new(since_utc);
+
+    // 1) Walk the `new` listing as far as Reddit allows
+    let url = format!("{base_url}/r/{subreddit}/new.json?raw_json=1&limit=100");
+    let complete = walk_listing(&url, true, &mut collected, fetch).await?;
+
+    // 2) The listing ran out before the cutoff, so page through the others
+    if !complete {
+        for listing in FALLBACK_LISTINGS {
+            let url = format!("{base_url}/r/{subreddit}/{listing}&raw_json=1&limit=100");
+            walk_listing(&url, false, &mut collected, fetch).await?;
+        }
+    }
+    Ok(BackfillReport {
+        links: collected.into_links(),
+        complete,
+    })
+}
+
+/// Page through `url` by its `after` cursor until it ends or hits [`LISTING_LIMIT`],
+/// or, if `stop_at_cutoff`, until a post older than the cutoff appears.
+///
+/// Returns whether the cutoff was reached.
+async fn walk_listing<F, Fut>(
+    url: &str,
+    stop_at_cutoff: bool,
+    collected: &mut Collected,
+    fetch: F,
+) -> eyre::Result<bool>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = eyre::Result<RedditListing>>,
+{
+    let mut listed = 0;
+    let mut reached_cutoff = false;
+    let mut after: Option<String> = None;
+    loop {
+        let page_url = match &after {
+            Some(a) => format!("{url}&after={a}"),
+            None => url.to_string(),
+        };
+        let listing = fetch(page_url).await?;
+        after = listing.after.clone();
+        let absorbed = collected.absorb(listing);
+        listed += absorbed.listed;
+        reached_cutoff |= absorbed.reached_cutoff;
+        if (stop_at_cutoff && reached_cutoff)
+            || after.is_none()
+            || absorbed.listed == 0
+            || listed >= LISTING_LIMIT
+        {
+            return Ok(reached_cutoff);
+        }
+    }
+}
+
+/// Links gathered so far, deduplicated by id and limited to those after the cutoff.
+struct Collected {
+    since_utc: f64,
+    seen: HashSet<String>,
+    links: Vec<RedditLink>,
+}
+
+struct Absorbed {
+    /// Links in the listing, including duplicates and ones past the cutoff.
+    listed: usize,
+    /// Whether the listing contained a link older than the cutoff.
+    reached_cutoff: bool,
+}
+
+impl Collected {
+    fn new(since_utc: f64) -> Self {
+        Self {
+            since_utc,
+            seen: HashSet::new(),
+            links: vec![],
+        }
+    }
+
+    fn absorb(&mut self, listing: RedditListing) -> Absorbed {
+        let mut absorbed = Absorbed {
+            listed: 0,
+            reached_cutoff: false,
+        };
+        for thing in listing.children {
+            let RedditThing::Link(link) = thing else {
+                continue;
+            };
+            absorbed.listed += 1;
+            if link.created_utc < self.since_utc {
+                // Pinned posts can be old, so they don't mark the end of the range
+                absorbed.reached_cutoff |= !link.stickied;
+                continue;
+            }
+            if self.seen.insert(link.id.clone()) {
+                self.links.push(link);
+            }
+        }
+        absorbed
+    }
+
+    fn into_links(mut self) -> Vec<RedditLink> {
+        self.links
+            .sort_by(|a, b| b.created_utc.total_cmp(&a.created_utc));
+        self.links
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::RedditResponse;
+
+    use super::*;
+
+    fn example_listing() -> eyre::Result<RedditListing> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.json")?;
+        let RedditResponse::Listing(listing) = serde_json::from_str(&text)?;
+        Ok(listing)
+    }
+
+    #[test]
+    fn stops_at_cutoff_and_dedupes() -> eyre::Result<()> {
+        let listing = example_listing()?;
+        let mut times: Vec<f64> = listing
+            .children
+            .iter()
+            .filter_map(|thing| match thing {
+                RedditThing::Link(link) if !link.stickied => Some(link.created_utc),
+                _ => None,
+            })
+            .collect();
+        times.sort_by(f64::total_cmp);
+        let cutoff = times[times.len() / 2];
+
+        let mut collected = Collected::new(cutoff);
+        let first = collected.absorb(listing);
+        assert!(first.reached_cutoff);
+        let kept = collected.links.len();
+        assert!(kept > 0 && kept < first.listed);
+
+        let second = collected.absorb(example_listing()?);
+        assert_eq!(second.listed, first.listed);
+        assert_eq!(collected.links.len(), kept);
+
+        let links = collected.into_links();
+        assert!(links.iter().all(|l| l.created_utc >= cutoff));
+        assert!(links
+            .windows(2)
+            .all(|w| w[0].created_utc >= w[1].created_utc));
+        Ok(())
+    }
+
+    /// The example listing sorted newest first and split into pages of
+    /// `per_page`, each pointing at the next with an `after` cursor.
+    fn example_pages(per_page: usize) -> eyre::Result<Vec<String>> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.json")?;
+        let listing: serde_json::Value = serde_json::from_str(&text)?;
+        let mut children = listing["data"]["children"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        children.sort_by(|a, b| {
+            let created = |t: &serde_json::Value| t["data"]["created_utc"].as_f64().unwrap_or(0.0);
+            created(b).total_cmp(&created(a))
+        });
+        let chunks: Vec<_> = children.chunks(per_page).collect();
+        Ok(chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut page = listing.clone();
+                page["data"]["children"] = serde_json::Value::from(chunk.to_vec());
+                page["data"]["after"] = if i + 1 < chunks.len() {
+                    format!("page{}", i + 1).into()
+                } else {
+                    serde_json::Value::Null
+                };
+                page.to_string()
+            })
+            .collect())
+    }
+
+    /// The page of `pages` that `url` asks for by its `after` cursor.
+    fn page_for(pages: &[String], url: &str) -> eyre::Result<RedditListing> {
+        let page = url
+            .split_once("&after=page")
+            .map_or(0, |(_, i)| i.parse().unwrap());
+        let RedditResponse::Listing(listing) = serde_json::from_str(&pages[page])?;
+        Ok(listing)
+    }
+
+    #[tokio::test]
+    async fn walks_by_cursor() -> eyre::Result<()> {
+        let pages = example_pages(5)?;
+        let requested = std::sync::Mutex::new(Vec::new());
+        let fetch = |url: String| {
+            let listing = page_for(&pages, &url);
+            requested.lock().unwrap().push(url);
+            async move { listing }
+        };
+        let url = "https://www.reddit.com/r/test/new.json?limit=5";
+
+        // Everything is newer than the cutoff, so every page is walked
+        let mut collected = Collected::new(0.0);
+        assert!(!walk_listing(url, true, &mut collected, fetch).await?);
+        assert_eq!(requested.lock().unwrap().len(), pages.len());
+        assert_eq!(requested.lock().unwrap()[1], format!("{url}&after=page1"));
+        let all = collected.links.len();
+
+        // A cutoff in the second page stops the walk there
+        let mut times: Vec<f64> = collected.links.iter().map(|l| l.created_utc).collect();
+        times.sort_by(|a, b| b.total_cmp(a));
+        requested.lock().unwrap().clear();
+        let mut collected = Collected::new(times[7]);
+        assert!(walk_listing(url, true, &mut collected, fetch).await?);
+        assert_eq!(requested.lock().unwrap().len(), 2);
+        assert!(collected.links.len() < all);
+
+        // Listings in no time order are walked to the end regardless
+        requested.lock().unwrap().clear();
+        let mut collected = Collected::new(times[7]);
+        assert!(walk_listing(url, false, &mut collected, fetch).await?);
+        assert_eq!(requested.lock().unwrap().len(), pages.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_whether_complete() -> eyre::Result<()> {
+        let pages = example_pages(5)?;
+        let requested = std::sync::Mutex::new(Vec::new());
+        let fetch = |url: String| {
+            let listing = page_for(&pages, &url);
+            requested.lock().unwrap().push(url);
+            async move { listing }
+        };
+        let subreddit = SubredditSlug::new("test");
+
+        // `new` never reaches the cutoff, so the other listings are walked too
+        let report = backfill_with("http://reddit.test", &subreddit, 0.0, fetch).await?;
+        assert!(!report.complete);
+        assert_eq!(
+            requested.lock().unwrap().len(),
+            pages.len() * (1 + FALLBACK_LISTINGS.len())
+        );
+        let times: Vec<f64> = report.links.iter().map(|l| l.created_utc).collect();
+        assert!(times.windows(2).all(|w| w[0] >= w[1]));
+
+        // A cutoff within `new` is everything there is
+        requested.lock().unwrap().clear();
+        let report = backfill_with("http://reddit.test", &subreddit, times[7], fetch).await?;
+        assert!(report.complete);
+        assert_eq!(requested.lock().unwrap().len(), 2);
+        assert!(requested.lock().unwrap()[0].starts_with("http://reddit.test/r/test/new.json"));
+        Ok(())
+    }
+}
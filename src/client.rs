@@ -1,11 +1,18 @@
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::RwLock;
 
+use futures::stream;
+use futures::Stream;
+use futures::StreamExt;
 use reqwest::header::HeaderMap;
 use reqwest::header::HeaderValue;
 use reqwest::header::USER_AGENT;
+use serde::de::IgnoredAny;
 
 use crate::models::RedditComment;
 use crate::models::RedditLink;
+use crate::models::RedditListing;
 use crate::models::RedditResponse;
 use crate::models::RedditThing;
 use crate::rate_limit::rate_limited_fetch;
@@ -28,6 +35,73 @@ impl SubredditSlug {
     }
 }
 
+/// Where responses are cached unless [`set_cache_dir`] says otherwise.
+pub const DEFAULT_CACHE_DIR: &str = "target/cache";
+
+static CACHE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Cache responses under `dir` instead of [`DEFAULT_CACHE_DIR`].
+pub fn set_cache_dir(dir: impl Into<PathBuf>) {
+    *CACHE_DIR.write().unwrap_or_else(|e| e.into_inner()) = Some(dir.into());
+}
+
+pub fn cache_dir() -> PathBuf {
+    CACHE_DIR
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR))
+}
+
+/// Where requests go unless [`set_base_url`] says otherwise.
+pub const DEFAULT_BASE_URL: &str = "https://www.reddit.com";
+
+static BASE_URL: RwLock<Option<String>> = RwLock::new(None);
+
+/// Send requests to `url` instead of [`DEFAULT_BASE_URL`], such as a stand-in server.
+pub fn set_base_url(url: impl Into<String>) {
+    *BASE_URL.write().unwrap_or_else(|e| e.into_inner()) = Some(url.into());
+}
+
+pub fn base_url() -> String {
+    BASE_URL
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+}
+
+/// The order of a subreddit listing, e.g. `/r/{sub}/top.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListingSort {
+    #[default]
+    Hot,
+    New,
+    Top,
+    Rising,
+    Controversial,
+}
+
+impl ListingSort {
+    pub const ALL: [ListingSort; 5] = [
+        ListingSort::Hot,
+        ListingSort::New,
+        ListingSort::Top,
+        ListingSort::Rising,
+        ListingSort::Controversial,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListingSort::Hot => "hot",
+            ListingSort::New => "new",
+            ListingSort::Top => "top",
+            ListingSort::Rising => "rising",
+            ListingSort::Controversial => "controversial",
+        }
+    }
+}
+
 pub async fn fetch_subreddit_posts(subreddit: SubredditSlug) -> eyre::Result<Vec<RedditLink>> {
     let cache_file = PathBuf::from("target/response.json");
     let response_text = match tokio::fs::try_exists(&cache_file).await {
@@ -75,45 +149,10 @@ pub async fn fetch_subreddit_posts_paginated(
     let mut after: Option<String> = None;
 
     // We only need one client:
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        USER_AGENT,
-        HeaderValue::from_str("windows:ca.teamdman.myredditapp:v0.0.1 (by /u/TeamDman)")?,
-    );
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()?;
+    let client = build_client()?;
 
     for page_idx in 0..pages {
-        // Build the cache path
-        let cache_file = format!("target/cache/subreddit/{}_{}.json", subreddit, page_idx);
-        let cache_path = PathBuf::from(&cache_file);
-
-        // Build the URL with after param
-        let mut url = format!("https://www.reddit.com/r/{}.json?raw_json=1", subreddit);
-        if let Some(ref a) = after {
-            url = format!("{}&after={}", url, a);
-        }
-
-        // Try cache
-        let response_text = if tokio_fs::try_exists(&cache_path).await.unwrap_or(false) {
-            // If the file exists, read from the cache
-            tokio_fs::read_to_string(&cache_path).await?
-        } else {
-            // Otherwise do a network fetch, but with our rate limit
-            let response_text = rate_limited_fetch(&client, &url).await?;
-            // Save to cache
-            if let Some(parent) = cache_path.parent() {
-                tokio_fs::create_dir_all(parent).await?;
-            }
-            tokio_fs::write(&cache_path, &response_text).await?;
-            response_text
-        };
-
-        // Deserialize
-        let jd = &mut serde_json::Deserializer::from_str(&response_text);
-        let response: RedditResponse = serde_path_to_error::deserialize(jd)?;
-        let RedditResponse::Listing(listing) = response;
+        let listing = fetch_subreddit_page(&client, &subreddit, page_idx, after.as_deref()).await?;
 
         // Collect the links
         listing.children.into_iter().for_each(|thing| {
@@ -132,31 +171,331 @@ pub async fn fetch_subreddit_posts_paginated(
     }
     Ok(all_links)
 }
+
+/// Fetch a single page of a subreddit listing, starting after the `after` fullname.
+pub async fn fetch_subreddit_page(
+    client: &reqwest::Client,
+    subreddit: &SubredditSlug,
+    page_idx: usize,
+    after: Option<&str>,
+) -> eyre::Result<RedditListing> {
+    fetch_sorted_subreddit_page(client, subreddit, ListingSort::Hot, page_idx, after).await
+}
+
+/// Like [`fetch_subreddit_page`], in `sort` order.
+///
+/// `new` and `rising` pages shift every time a post arrives, so they are
+/// always fetched and never cached.
+pub async fn fetch_sorted_subreddit_page(
+    client: &reqwest::Client,
+    subreddit: &SubredditSlug,
+    sort: ListingSort,
+    page_idx: usize,
+    after: Option<&str>,
+) -> eyre::Result<RedditListing> {
+    let (url, cache_path) = subreddit_page_location(subreddit, sort, page_idx, after);
+    match cache_path {
+        Some(cache_path) => cached_listing(client, &url, cache_path).await,
+        None => fetch_listing(client, &url).await,
+    }
+}
+
+/// Like [`fetch_sorted_subreddit_page`], but always hits the network and overwrites the cache.
+pub async fn refresh_sorted_subreddit_page(
+    client: &reqwest::Client,
+    subreddit: &SubredditSlug,
+    sort: ListingSort,
+    page_idx: usize,
+    after: Option<&str>,
+) -> eyre::Result<RedditListing> {
+    let (url, cache_path) = subreddit_page_location(subreddit, sort, page_idx, after);
+    match cache_path {
+        Some(cache_path) => parse_listing(&fetch_into_cache(client, &url, &cache_path).await?),
+        None => fetch_listing(client, &url).await,
+    }
+}
+
+/// The URL of a subreddit page and where it's cached, if it is.
+fn subreddit_page_location(
+    subreddit: &SubredditSlug,
+    sort: ListingSort,
+    page_idx: usize,
+    after: Option<&str>,
+) -> (String, Option<PathBuf>) {
+    // Hot pages keep their original `{subreddit}_{page}.json` cache name
+    let (cache_name, mut url) = match sort {
+        ListingSort::Hot => (
+            Some(format!("{}_{}.json", subreddit, page_idx)),
+            format!("{}/r/{}.json?raw_json=1", base_url(), subreddit),
+        ),
+        _ => (
+            matches!(sort, ListingSort::Top | ListingSort::Controversial)
+                .then(|| format!("{}_{}_{}.json", subreddit, sort.as_str(), page_idx)),
+            format!(
+                "{}/r/{}/{}.json?raw_json=1",
+                base_url(),
+                subreddit,
+                sort.as_str()
+            ),
+        ),
+    };
+    if let Some(a) = after {
+        url = format!("{}&after={}", url, a);
+    }
+    let cache_path = cache_name.map(|name| cache_dir().join("subreddit").join(name));
+    (url, cache_path)
+}
+
+/// Fetch a page of a user's posts, or of their comments if `comments` is set.
+pub async fn fetch_user_page(
+    client: &reqwest::Client,
+    user: &str,
+    comments: bool,
+    page_idx: usize,
+    after: Option<&str>,
+) -> eyre::Result<RedditListing> {
+    let (url, cache_path) = user_page_location(user, comments, page_idx, after);
+    cached_listing(client, &url, cache_path).await
+}
+
+/// Like [`fetch_user_page`], but always hits the network and overwrites the cache.
+pub async fn refresh_user_page(
+    client: &reqwest::Client,
+    user: &str,
+    comments: bool,
+    page_idx: usize,
+    after: Option<&str>,
+) -> eyre::Result<RedditListing> {
+    let (url, cache_path) = user_page_location(user, comments, page_idx, after);
+    parse_listing(&fetch_into_cache(client, &url, &cache_path).await?)
+}
+
+/// The URL of a page of a user's posts or comments and where it's cached.
+fn user_page_location(
+    user: &str,
+    comments: bool,
+    page_idx: usize,
+    after: Option<&str>,
+) -> (String, PathBuf) {
+    let kind = if comments { "comments" } else { "submitted" };
+    let mut url = format!("{}/user/{user}/{kind}.json?raw_json=1", base_url());
+    if let Some(a) = after {
+        url = format!("{url}&after={a}");
+    }
+    let cache_path = cache_dir()
+        .join("users")
+        .join(format!("{user}_{kind}_{page_idx}.json"));
+    (url, cache_path)
+}
+
+/// Read the listing cached at `cache_path`, or fetch `url` and cache it there.
+async fn cached_listing(
+    client: &reqwest::Client,
+    url: &str,
+    cache_path: PathBuf,
+) -> eyre::Result<RedditListing> {
+    // Try cache
+    let response_text = if tokio_fs::try_exists(&cache_path).await.unwrap_or(false) {
+        // If the file exists, read from the cache
+        tokio_fs::read_to_string(&cache_path).await?
+    } else {
+        // Otherwise do a network fetch, but with our rate limit
+        fetch_into_cache(client, url, &cache_path).await?
+    };
+    parse_listing(&response_text)
+}
+
+/// Fetch `url` and save the response text to `cache_path`.
+async fn fetch_into_cache(
+    client: &reqwest::Client,
+    url: &str,
+    cache_path: &Path,
+) -> eyre::Result<String> {
+    let response_text = rate_limited_fetch(client, url).await?;
+    if let Some(parent) = cache_path.parent() {
+        tokio_fs::create_dir_all(parent).await?;
+    }
+    tokio_fs::write(cache_path, &response_text).await?;
+    Ok(response_text)
+}
+
+fn parse_listing(response_text: &str) -> eyre::Result<RedditListing> {
+    let jd = &mut serde_json::Deserializer::from_str(response_text);
+    let response: RedditResponse = serde_path_to_error::deserialize(jd)?;
+    let RedditResponse::Listing(listing) = response;
+    Ok(listing)
+}
+
+/// Fetch and parse a listing endpoint, e.g. `/r/{sub}/new.json`, without caching.
+///
+/// For listings that move as new posts arrive, where a page-number cache would
+/// go stale.
+pub async fn fetch_listing(client: &reqwest::Client, url: &str) -> eyre::Result<RedditListing> {
+    parse_listing(&rate_limited_fetch(client, url).await?)
+}
+
+pub const DEFAULT_USER_AGENT: &str = "windows:ca.teamdman.myredditapp:v0.0.1 (by /u/TeamDman)";
+
+/// A client that sends our user agent, which Reddit requires of API consumers.
+pub fn build_client() -> eyre::Result<reqwest::Client> {
+    build_client_with_user_agent(DEFAULT_USER_AGENT)
+}
+
+/// Like [`build_client`], identifying as `user_agent` instead.
+pub fn build_client_with_user_agent(user_agent: &str) -> eyre::Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_str(user_agent)?);
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?;
+    Ok(client)
+}
+
 pub async fn fetch_link_comments(
     client: &reqwest::Client,
     post_id: &str,
     link_url: &str,
 ) -> eyre::Result<Vec<RedditComment>> {
-    // "target/cache/posts/{post_id}.json"
-    let cache_file = format!("target/cache/posts/{}.json", post_id);
-    let cache_path = PathBuf::from(&cache_file);
-
-    let response_text = if tokio::fs::try_exists(&cache_path).await.unwrap_or(false) {
-        tokio::fs::read_to_string(&cache_path).await?
+    let cache_path = link_comments_cache_path(post_id);
+    if tokio::fs::try_exists(&cache_path).await.unwrap_or(false) {
+        let response_text = tokio::fs::read_to_string(&cache_path).await?;
+        parse_link_comments(&response_text)
     } else {
-        let url = format!("{}.json?raw_json=1", link_url);
-        // Rate-limited fetch
-        let response_text = rate_limited_fetch(client, &url).await?;
+        refresh_link_comments(client, post_id, link_url).await
+    }
+}
 
-        if let Some(parent) = cache_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-        tokio::fs::write(&cache_path, &response_text).await?;
-        response_text
-    };
+/// Like [`fetch_link_comments`], but always hits the network and overwrites the cache.
+pub async fn refresh_link_comments(
+    client: &reqwest::Client,
+    post_id: &str,
+    link_url: &str,
+) -> eyre::Result<Vec<RedditComment>> {
+    let cache_path = link_comments_cache_path(post_id);
+    let url = format!("{}.json?raw_json=1", link_url);
+    // Rate-limited fetch
+    let response_text = rate_limited_fetch(client, &url).await?;
 
-    let jd = &mut serde_json::Deserializer::from_str(&response_text);
-    let response: (RedditResponse, RedditResponse) = serde_path_to_error::deserialize(jd)?;
+    if let Some(parent) = cache_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&cache_path, &response_text).await?;
+    parse_link_comments(&response_text)
+}
+
+/// Drop a post's cached comments, so the next fetch goes to the network.
+pub async fn forget_link_comments(post_id: &str) -> eyre::Result<()> {
+    match tokio::fs::remove_file(link_comments_cache_path(post_id)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn link_comments_cache_path(post_id: &str) -> PathBuf {
+    // "{cache_dir}/posts/{post_id}.json"
+    cache_dir().join("posts").join(format!("{}.json", post_id))
+}
+
+/// The post id in a fullname (`t3_1iambwd`), permalink or URL of a post, or a bare id.
+pub fn parse_post_id(post: &str) -> Option<String> {
+    let post = post.trim().trim_end_matches('/');
+    if let Some(rest) = post.split("/comments/").nth(1) {
+        return rest.split('/').next().map(str::to_string);
+    }
+    if let Some(id) = post.strip_prefix("https://redd.it/") {
+        return Some(id.to_string());
+    }
+    let id = post.strip_prefix("t3_").unwrap_or(post);
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())).then(|| id.to_string())
+}
+
+/// Reddit accepts at most this many fullnames per `/api/info` request.
+pub const INFO_BATCH_SIZE: usize = 100;
+
+/// Look up things by fullname (e.g. `t3_1iambwd`) through `/api/info`, batching
+/// requests so each one asks for at most [`INFO_BATCH_SIZE`] things.
+pub async fn fetch_info(
+    client: &reqwest::Client,
+    fullnames: &[String],
+) -> eyre::Result<Vec<RedditThing>> {
+    let mut things = Vec::with_capacity(fullnames.len());
+    for chunk in fullnames.chunks(INFO_BATCH_SIZE) {
+        let url = format!(
+            "{}/api/info.json?raw_json=1&id={}",
+            base_url(),
+            chunk.join(",")
+        );
+        let listing = fetch_listing(client, &url).await?;
+        things.extend(listing.children);
+    }
+    Ok(things)
+}
+
+/// Other posts of the same URL, from `/duplicates/{id}`. `id` is the post id without `t3_`.
+pub async fn fetch_duplicates(client: &reqwest::Client, id: &str) -> eyre::Result<Vec<RedditLink>> {
+    let url = format!("{}/duplicates/{id}.json?raw_json=1&limit=100", base_url());
+    let response_text = rate_limited_fetch(client, &url).await?;
+    parse_duplicates(&response_text)
+}
+
+/// Parse the `[original, duplicates]` pair of listings returned by `/duplicates/{id}`.
+pub fn parse_duplicates(response_text: &str) -> eyre::Result<Vec<RedditLink>> {
+    let jd = &mut serde_json::Deserializer::from_str(response_text);
+    let response: (IgnoredAny, RedditResponse) = serde_path_to_error::deserialize(jd)?;
+    let (_original, RedditResponse::Listing(listing)) = response;
+    Ok(listing
+        .children
+        .into_iter()
+        .filter_map(|thing| match thing {
+            RedditThing::Link(link) => Some(link),
+            _ => None,
+        })
+        .collect())
+}
+
+/// A post whose comments can be fetched, such as a [`RedditLink`].
+pub trait PostRef {
+    fn post_id(&self) -> &str;
+    /// The path of the post, e.g. `/r/{sub}/comments/{id}/{slug}/`.
+    fn permalink(&self) -> &str;
+}
+
+impl PostRef for RedditLink {
+    fn post_id(&self) -> &str {
+        &self.id
+    }
+
+    fn permalink(&self) -> &str {
+        &self.permalink
+    }
+}
+
+/// Fetch the comment trees for many links, running up to `concurrency` fetches at once.
+///
+/// Every network request still goes through the shared rate limiter, so this
+/// overlaps response latency rather than raising the request rate. Results are
+/// yielded as they complete, and a failure for one link does not stop the others.
+pub fn fetch_links_comments<'a, P: PostRef + 'a>(
+    client: &'a reqwest::Client,
+    links: impl IntoIterator<Item = &'a P> + 'a,
+    concurrency: usize,
+) -> impl Stream<Item = (&'a P, eyre::Result<Vec<RedditComment>>)> + 'a {
+    stream::iter(links)
+        .map(move |link| async move {
+            let link_url = format!("{}{}", base_url(), link.permalink());
+            let comments = fetch_link_comments(client, link.post_id(), &link_url).await;
+            (link, comments)
+        })
+        .buffer_unordered(concurrency.max(1))
+}
+
+/// Parse the `[link, comments]` pair returned by a post's `.json` endpoint.
+///
+/// Only the top-level comments are built; each `replies` listing is kept as
+/// raw JSON until it is asked for.
+pub fn parse_link_comments(response_text: &str) -> eyre::Result<Vec<RedditComment>> {
+    let jd = &mut serde_json::Deserializer::from_str(response_text);
+    let response: (IgnoredAny, RedditResponse) = serde_path_to_error::deserialize(jd)?;
     let (_sub, comments) = response;
     let RedditResponse::Listing(listing) = comments;
     let comms = listing
@@ -179,6 +518,8 @@ mod tests {
     use serde::Deserialize;
 
     use crate::models::RedditListing;
+    use crate::test_support::example_links;
+    use crate::test_support::temp_cache_dir;
 
     use super::*;
 
@@ -211,6 +552,86 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_example_post_comments() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let comments = parse_link_comments(&text)?;
+        assert!(!comments.is_empty());
+        let with_replies = comments
+            .iter()
+            .find_map(|c| c.replies.as_ref())
+            .expect("some comment has replies");
+        assert!(!with_replies.is_parsed());
+        let RedditResponse::Listing(listing) = with_replies.get()?;
+        assert!(listing
+            .children
+            .iter()
+            .any(|thing| matches!(thing, RedditThing::Comment(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn post_ids() {
+        for post in [
+            "1iambwd",
+            "t3_1iambwd",
+            "/r/bapcsalescanada/comments/1iambwd/amd_ryzen_7_7700_23237_aliexpress/",
+            "https://www.reddit.com/r/bapcsalescanada/comments/1iambwd/",
+            "https://redd.it/1iambwd",
+        ] {
+            assert_eq!(parse_post_id(post).as_deref(), Some("1iambwd"), "{post}");
+        }
+        assert_eq!(parse_post_id("not an id"), None);
+    }
+
+    #[tokio::test]
+    async fn user_comments_page() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/user.comments.json")?;
+        let users_dir = temp_cache_dir().join("users");
+        tokio::fs::create_dir_all(&users_dir).await?;
+        tokio::fs::write(users_dir.join("user_test_comments_0.json"), &text).await?;
+
+        let client = reqwest::Client::new();
+        let listing = fetch_user_page(&client, "user_test", true, 0, None).await?;
+        let comments: Vec<&RedditComment> = listing
+            .children
+            .iter()
+            .filter_map(|thing| match thing {
+                RedditThing::Comment(comment) => Some(comment),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(comments.len(), listing.children.len());
+        assert!(comments.iter().all(|c| c.author == "hats_yyz"));
+        assert!(comments.iter().all(|c| c.link_id == "t3_1iambwd"));
+        // User listings leave out depth and replies
+        assert!(comments.iter().all(|c| c.depth == 0 && c.replies.is_none()));
+        assert!(comments.iter().any(|c| c.parent_id.starts_with("t1_")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_comment_errors_are_per_link() -> eyre::Result<()> {
+        let mut links: Vec<RedditLink> = example_links()?.into_iter().take(3).collect();
+
+        let post = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let posts_dir = temp_cache_dir().join("posts");
+        tokio::fs::create_dir_all(&posts_dir).await?;
+        for (i, link) in links.iter_mut().enumerate() {
+            link.id = format!("batch_test_{i}");
+            let body = if i == 1 { "not json" } else { post.as_str() };
+            tokio::fs::write(posts_dir.join(format!("{}.json", link.id)), body).await?;
+        }
+
+        let client = reqwest::Client::new();
+        let results: Vec<_> = fetch_links_comments(&client, &links, 2).collect().await;
+        assert_eq!(results.len(), 3);
+        for (link, comments) in results {
+            assert_eq!(comments.is_err(), link.id == "batch_test_1", "{}", link.id);
+        }
+        Ok(())
+    }
+
     #[test]
     fn idk() -> eyre::Result<()> {
         let x = "[1,2,3]";
@@ -243,12 +664,13 @@ mod tests {
   },
   "kind": "Listing"
 }]"#;
-        let response = serde_json::from_str::<(RedditResponse, RedditResponse)>(x)?;
+        let _response = serde_json::from_str::<(RedditResponse, RedditResponse)>(x)?;
         Ok(())
     }
     #[test]
     fn lines() -> eyre::Result<()> {
         #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
         struct Bruh {
             bruh: String,
         }
@@ -264,7 +686,10 @@ mod tests {
             bail!("Expected an error, got {:?}", response);
         };
         println!("{}", e);
-        assert_eq!(e.to_string(), "expected value at line 2 column 5");
+        assert_eq!(
+            e.to_string(),
+            "invalid type: floating point `1.23`, expected a string at line 4 column 17"
+        );
         Ok(())
     }
 }
@@ -0,0 +1,101 @@
+use tracing::warn;
+
+use crate::lazy::LazyError;
+use crate::models::RedditComment;
+
+/// The comments on a post, as returned by [`fetch_link_comments`](crate::client::fetch_link_comments).
+#[derive(Debug, PartialEq)]
+pub struct CommentTree {
+    /// Top-level comments; replies hang off each comment's `replies`.
+    pub comments: Vec<RedditComment>,
+}
+
+impl From<Vec<RedditComment>> for CommentTree {
+    fn from(comments: Vec<RedditComment>) -> Self {
+        Self { comments }
+    }
+}
+
+impl CommentTree {
+    /// Every comment in the tree, depth first, parents before their replies.
+    ///
+    /// Replies whose listing fails to parse are skipped with a warning; see
+    /// [`CommentTree::unreadable`] for which comments that affects.
+    pub fn iter(&self) -> CommentTreeIter<'_> {
+        CommentTreeIter {
+            stack: self.comments.iter().rev().collect(),
+        }
+    }
+
+    /// Find a comment at any depth by fullname (`t1_...`).
+    pub fn find(&self, name: &str) -> Option<&RedditComment> {
+        self.iter().find(|comment| comment.name == name)
+    }
+
+    /// Comments whose `replies` listing failed to parse, with the error.
+    pub fn unreadable(&self) -> impl Iterator<Item = (&RedditComment, LazyError)> {
+        self.iter()
+            .filter_map(|comment| comment.reply_comments().err().map(|e| (comment, e)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a CommentTree {
+    type Item = &'a RedditComment;
+    type IntoIter = CommentTreeIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct CommentTreeIter<'a> {
+    stack: Vec<&'a RedditComment>,
+}
+
+impl<'a> Iterator for CommentTreeIter<'a> {
+    type Item = &'a RedditComment;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let comment = self.stack.pop()?;
+        let start = self.stack.len();
+        match comment.reply_comments() {
+            Ok(replies) => self.stack.extend(replies),
+            Err(e) => warn!("Skipping unreadable replies to {}: {e}", comment.name),
+        }
+        self.stack[start..].reverse();
+        Some(comment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::parse_link_comments;
+
+    use super::*;
+
+    #[test]
+    fn walks_every_depth_in_order() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let tree = CommentTree::from(parse_link_comments(&text)?);
+        let comments: Vec<_> = tree.iter().collect();
+        assert!(comments.len() > tree.comments.len());
+        assert_eq!(comments[0].name, tree.comments[0].name);
+        // Every reply comes after its parent
+        for (i, comment) in comments.iter().enumerate() {
+            if let Some(parent) = comments.iter().position(|c| c.name == comment.parent_id) {
+                assert!(parent < i);
+                assert_eq!(comments[parent].depth + 1, comment.depth);
+            }
+        }
+        assert_eq!(tree.unreadable().count(), 0);
+        Ok(())
+    }
+}
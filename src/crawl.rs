@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use futures::StreamExt;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::client::fetch_links_comments;
+use crate::client::fetch_subreddit_page;
+use crate::client::forget_link_comments;
+use crate::client::PostRef;
+use crate::client::SubredditSlug;
+use crate::models::RedditThing;
+use crate::persist::load_json;
+use crate::persist::save_json;
+
+/// Everything needed to pick a crawl back up after it was interrupted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CrawlCheckpoint {
+    pub subreddit: String,
+    pub pages_target: usize,
+    pub pages_fetched: usize,
+    /// Cursor for the next listing page.
+    pub after: Option<String>,
+    pub listing_done: bool,
+    /// Posts discovered in the listing whose comments have not been fetched yet.
+    pub pending: Vec<CrawlPost>,
+    /// Ids of posts whose comments were fetched.
+    pub completed: BTreeSet<String>,
+    /// Posts whose comment fetch failed, keyed by id.
+    pub failures: BTreeMap<String, CrawlFailure>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrawlPost {
+    pub id: String,
+    pub permalink: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrawlFailure {
+    pub post: CrawlPost,
+    pub error: String,
+    pub attempts: usize,
+}
+
+impl PostRef for CrawlPost {
+    fn post_id(&self) -> &str {
+        &self.id
+    }
+
+    fn permalink(&self) -> &str {
+        &self.permalink
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrawlProgress {
+    pub pages_fetched: usize,
+    pub pages_target: usize,
+    pub posts_pending: usize,
+    pub posts_completed: usize,
+    pub posts_failed: usize,
+}
+
+impl CrawlProgress {
+    pub fn is_done(&self) -> bool {
+        self.posts_pending == 0 && self.pages_fetched >= self.pages_target
+    }
+}
+
+impl std::fmt::Display for CrawlProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pages {}/{}, posts {} done, {} pending, {} failed",
+            self.pages_fetched,
+            self.pages_target,
+            self.posts_completed,
+            self.posts_pending,
+            self.posts_failed
+        )
+    }
+}
+
+/// How many posts' comments a crawl fetches at once unless told otherwise.
+pub const DEFAULT_CRAWL_CONCURRENCY: usize = 4;
+
+/// A subreddit crawl (listing pages, then every post's comments) that saves a
+/// checkpoint to disk after each step so it can be resumed.
+pub struct CrawlJob {
+    client: reqwest::Client,
+    checkpoint_path: PathBuf,
+    concurrency: usize,
+    pub state: CrawlCheckpoint,
+}
+
+impl CrawlJob {
+    pub fn new(
+        client: reqwest::Client,
+        subreddit: SubredditSlug,
+        pages: usize,
+        checkpoint_path: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            client,
+            checkpoint_path: checkpoint_path.into(),
+            concurrency: DEFAULT_CRAWL_CONCURRENCY,
+            state: CrawlCheckpoint {
+                subreddit: subreddit.to_string(),
+                pages_target: pages,
+                pages_fetched: 0,
+                after: None,
+                listing_done: pages == 0,
+                pending: vec![],
+                completed: BTreeSet::new(),
+                failures: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Load the checkpoint at `checkpoint_path`.
+    pub async fn resume(
+        client: reqwest::Client,
+        checkpoint_path: impl Into<PathBuf>,
+    ) -> eyre::Result<Self> {
+        let checkpoint_path = checkpoint_path.into();
+        let state = load_json(&checkpoint_path).await?;
+        Ok(Self {
+            client,
+            checkpoint_path,
+            concurrency: DEFAULT_CRAWL_CONCURRENCY,
+            state,
+        })
+    }
+
+    /// Fetch up to `concurrency` posts' comments at once. Requests still share
+    /// the rate limit; see [`fetch_links_comments`].
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Resume from `checkpoint_path` if it exists, otherwise start a new crawl.
+    pub async fn resume_or_new(
+        client: reqwest::Client,
+        subreddit: SubredditSlug,
+        pages: usize,
+        checkpoint_path: impl Into<PathBuf>,
+    ) -> eyre::Result<Self> {
+        let checkpoint_path = checkpoint_path.into();
+        if tokio::fs::try_exists(&checkpoint_path)
+            .await
+            .unwrap_or(false)
+        {
+            Self::resume(client, checkpoint_path).await
+        } else {
+            Ok(Self::new(client, subreddit, pages, checkpoint_path))
+        }
+    }
+
+    pub fn checkpoint_path(&self) -> &Path {
+        &self.checkpoint_path
+    }
+
+    pub fn progress(&self) -> CrawlProgress {
+        CrawlProgress {
+            pages_fetched: self.state.pages_fetched,
+            pages_target: if self.state.listing_done {
+                self.state.pages_fetched
+            } else {
+                self.state.pages_target
+            },
+            posts_pending: self.state.pending.len(),
+            posts_completed: self.state.completed.len(),
+            posts_failed: self.state.failures.len(),
+        }
+    }
+
+    /// Do one unit of work (a listing page, or the comments of the next
+    /// `concurrency` pending posts) and save the checkpoint.
+    ///
+    /// Returns `false` once there is nothing left to do. Comment fetch failures
+    /// are recorded rather than returned; listing failures are returned since
+    /// the crawl cannot continue without the page.
+    pub async fn step(&mut self) -> eyre::Result<bool> {
+        if !self.state.listing_done {
+            self.fetch_next_page().await?;
+        } else if !self.state.pending.is_empty() {
+            let batch_len = self.concurrency.min(self.state.pending.len());
+            let batch: Vec<CrawlPost> = self.state.pending.drain(..batch_len).collect();
+            let results: Vec<(CrawlPost, eyre::Result<()>)> =
+                fetch_links_comments(&self.client, &batch, self.concurrency)
+                    .map(|(post, result)| (post.clone(), result.map(drop)))
+                    .collect()
+                    .await;
+            for (post, result) in results {
+                if result.is_err() {
+                    // The cached response may be what failed, so the retry fetches it again
+                    forget_link_comments(&post.id).await?;
+                }
+                self.record(post, result);
+            }
+        } else {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    fn record(&mut self, post: CrawlPost, result: eyre::Result<()>) {
+        match result {
+            Ok(()) => {
+                self.state.failures.remove(&post.id);
+                self.state.completed.insert(post.id);
+            }
+            Err(e) => {
+                let attempts = self.state.failures.get(&post.id).map_or(0, |f| f.attempts);
+                self.state.failures.insert(
+                    post.id.clone(),
+                    CrawlFailure {
+                        post,
+                        error: format!("{e:#}"),
+                        attempts: attempts + 1,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Step until done, calling `on_progress` after every step.
+    pub async fn run(
+        &mut self,
+        mut on_progress: impl FnMut(&CrawlProgress),
+    ) -> eyre::Result<CrawlProgress> {
+        while self.step().await? {
+            on_progress(&self.progress());
+        }
+        Ok(self.progress())
+    }
+
+    /// Queue every failed post to be fetched again on the next steps.
+    pub async fn retry_failures(&mut self) -> eyre::Result<()> {
+        for failure in self.state.failures.values() {
+            if !self.state.pending.contains(&failure.post) {
+                self.state.pending.push(failure.post.clone());
+            }
+        }
+        self.save().await
+    }
+
+    async fn fetch_next_page(&mut self) -> eyre::Result<()> {
+        let subreddit = SubredditSlug::new(&self.state.subreddit);
+        let listing = fetch_subreddit_page(
+            &self.client,
+            &subreddit,
+            self.state.pages_fetched,
+            self.state.after.as_deref(),
+        )
+        .await?;
+        for thing in listing.children {
+            if let RedditThing::Link(link) = thing {
+                let post = CrawlPost {
+                    id: link.id,
+                    permalink: link.permalink,
+                };
+                if !self.state.completed.contains(&post.id) && !self.state.pending.contains(&post) {
+                    self.state.pending.push(post);
+                }
+            }
+        }
+        self.state.pages_fetched += 1;
+        self.state.after = listing.after;
+        self.state.listing_done =
+            self.state.after.is_none() || self.state.pages_fetched >= self.state.pages_target;
+        Ok(())
+    }
+
+    pub async fn save(&self) -> eyre::Result<()> {
+        save_json(&self.checkpoint_path, &self.state).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use crate::client::set_base_url;
+    use crate::test_support::temp_cache_dir;
+
+    use super::*;
+
+    /// An HTTP server that answers every request with `body`, recording the paths asked for.
+    async fn stand_in_reddit(body: String) -> eyre::Result<(String, Arc<Mutex<Vec<String>>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let recorded = paths.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                let head = String::from_utf8_lossy(&buf).to_string();
+                let path = head.split_whitespace().nth(1).unwrap_or_default();
+                recorded.lock().unwrap().push(path.to_string());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        Ok((url, paths))
+    }
+
+    #[tokio::test]
+    async fn resumes_and_retries_failures() -> eyre::Result<()> {
+        let post = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let (url, requested) = stand_in_reddit(post.clone()).await?;
+        set_base_url(url);
+        let cache = temp_cache_dir();
+        let posts_dir = cache.join("posts");
+        tokio::fs::create_dir_all(&posts_dir).await?;
+        let posts: Vec<CrawlPost> = (0..3)
+            .map(|i| CrawlPost {
+                id: format!("crawl_test_{i}"),
+                permalink: format!("/r/test/comments/crawl_test_{i}/"),
+            })
+            .collect();
+        for (i, p) in posts.iter().enumerate() {
+            let body = if i == 1 { "not json" } else { post.as_str() };
+            tokio::fs::write(posts_dir.join(format!("{}.json", p.id)), body).await?;
+        }
+
+        let checkpoint = cache.join("crawl").join("crawl_test.json");
+        let _ = tokio::fs::remove_file(&checkpoint).await;
+        let client = reqwest::Client::new();
+        let mut job = CrawlJob::new(client.clone(), SubredditSlug::new("test"), 0, &checkpoint)
+            .with_concurrency(2);
+        job.state.pending = posts.clone();
+
+        // Interrupt after the first two posts, one of which fails, then pick up from disk.
+        assert!(job.step().await?);
+        assert!(!posts_dir.join("crawl_test_1.json").exists());
+        let mut job = CrawlJob::resume(client.clone(), &checkpoint).await?;
+        assert_eq!(job.progress().posts_completed, 1);
+        assert_eq!(job.progress().posts_failed, 1);
+        assert_eq!(job.progress().posts_pending, 1);
+
+        let progress = job.run(|_| {}).await?;
+        assert!(progress.is_done());
+        assert_eq!(progress.posts_completed, 2);
+        assert_eq!(progress.posts_failed, 1);
+        assert_eq!(job.state.failures["crawl_test_1"].attempts, 1);
+        assert!(requested.lock().unwrap().is_empty());
+
+        // The retry goes to the network rather than the response that failed
+        job.retry_failures().await?;
+        let progress = job.run(|_| {}).await?;
+        assert_eq!(progress.posts_completed, 3);
+        assert_eq!(progress.posts_failed, 0);
+        assert_eq!(
+            *requested.lock().unwrap(),
+            ["/r/test/comments/crawl_test_1/.json?raw_json=1"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::links::Retailer;
+use crate::models::RedditLink;
+
+/// A deal parsed from a post in the style of r/bapcsalescanada, e.g.
+/// `[GPU] ASRock Radeon RX 6600 Challenger ($600 - $330 = $270) [Newegg Canada]`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Deal {
+    /// The leading `[TAG]`, uppercased, e.g. `CPU` or `CASE+PSU`.
+    pub category: Option<String>,
+    pub product: String,
+    /// The price after any discount.
+    pub price: Option<f64>,
+    /// The price before the discount, for titles like `($600 - $330 = $270)`.
+    pub original_price: Option<f64>,
+    pub currency: Currency,
+    /// The trailing `[Retailer]` as written, or else the link's domain.
+    pub retailer: Option<String>,
+    /// Tax and shipping remarks, e.g. `+tax`, `NO GST` or `free shipping`.
+    pub shipping_notes: Vec<String>,
+    pub coupon_codes: Vec<String>,
+    /// The post's flair, which the subreddit uses for status like `Expired`.
+    pub flair: Option<String>,
+    pub url: String,
+    /// How sure the parse is, from 0 to 1. Tagged titles with a price in
+    /// parentheses and a bracketed retailer score highest.
+    pub confidence: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Currency {
+    #[default]
+    Cad,
+    Usd,
+}
+
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*\[([^\[\]]+)\]\s*").unwrap());
+static TRAILING_GROUP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[\[(]\s*([^\[\]()]+?)\s*[\])}]\s*$").unwrap());
+static PAREN_GROUP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\(([^()]*)\)").unwrap());
+static AMOUNT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?").unwrap());
+static DOLLAR_AMOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\$\s*(\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?)|(\d+(?:\.\d+)?)\s*\$").unwrap()
+});
+static TRAILING_DECIMAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s(\d+\.\d{2})\s*$").unwrap());
+static BARE_PRICE_MATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[\d\s.,\-=+/]+$").unwrap());
+static SHIPPING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\bfree\s+ship\w*|\+\s*ship\w*|\+\s*tax\b|\bno\s+[gph]st\b|\btax\s+in\w*")
+        .unwrap()
+});
+static COUPON: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:(?:coupon|promo)(?:\s+code)?|code)\s*[:=]?\s*([A-Za-z0-9-]{4,20})\b")
+        .unwrap()
+});
+
+impl Deal {
+    /// Parse a post's title, flair and url. Returns `None` for posts that look
+    /// nothing like a deal, i.e. have neither a `[TAG]` nor a price.
+    pub fn from_link(link: &RedditLink) -> Option<Deal> {
+        let mut deal = Self::parse_title(&link.title)?;
+        deal.flair = link.link_flair_text.clone();
+        deal.url = link.url.clone();
+        if deal.retailer.is_none() && !link.is_self {
+            let domain = link.domain.trim_start_matches("www.");
+            if !domain.is_empty() {
+                deal.retailer = Some(domain.to_string());
+                deal.confidence += 0.1;
+            }
+        }
+        Some(deal)
+    }
+
+    /// The retailer for grouping, from the title's name for it or else the url's domain.
+    pub fn retailer_kind(&self) -> Option<Retailer> {
+        self.retailer
+            .as_deref()
+            .and_then(Retailer::from_name)
+            .or_else(|| {
+                let url = url::Url::parse(&self.url).ok()?;
+                Some(Retailer::from_domain(url.host_str()?))
+            })
+    }
+
+    /// Parse a title on its own, without the post's flair or url.
+    pub fn parse_title(title: &str) -> Option<Deal> {
+        let title = decode_entities(title);
+        let mut confidence = 0.0;
+        let mut rest = title.as_str();
+
+        let category = TAG.captures(rest).map(|c| {
+            let tag = c[1].trim().to_uppercase();
+            rest = &rest[c.get(0).unwrap().end()..];
+            tag
+        });
+        if category.is_some() {
+            confidence += 0.25;
+        }
+
+        let mut retailer = None;
+        if let Some(c) = TRAILING_GROUP.captures(rest) {
+            if !is_price_group(&c[1]) {
+                retailer = Some(c[1].to_string());
+                rest = &rest[..c.get(0).unwrap().start()];
+                confidence += 0.2;
+            }
+        }
+
+        let mut shipping_notes = Vec::new();
+        let mut product = rest.to_string();
+        let mut price = None;
+        let mut original_price = None;
+        if let Some(group) = PAREN_GROUP
+            .captures_iter(rest)
+            .filter(|c| is_price_group(&c[1]))
+            .last()
+        {
+            let whole = group.get(0).unwrap();
+            let (final_price, original) = parse_price_group(&group[1]);
+            price = final_price;
+            original_price = original;
+            if let Some(note) = leftover_note(&group[1]) {
+                shipping_notes.push(note);
+            }
+            product = format!("{} {}", &rest[..whole.start()], &rest[whole.end()..]);
+        } else if let Some(m) = DOLLAR_AMOUNT.captures(rest) {
+            let amount = m.get(1).or(m.get(2)).unwrap().as_str();
+            price = parse_amount(amount);
+            product = rest[..m.get(0).unwrap().start()].to_string();
+        } else if let Some(m) = TRAILING_DECIMAL.captures(rest) {
+            price = parse_amount(&m[1]);
+            product = rest[..m.get(0).unwrap().start()].to_string();
+        }
+        if price.is_some() {
+            confidence += 0.35;
+        }
+        if category.is_none() && price.is_none() {
+            return None;
+        }
+        if matches!((price, original_price), (Some(p), Some(o)) if o > p) {
+            confidence += 0.1;
+        }
+
+        let product = collapse_whitespace(&product)
+            .trim_matches(|c: char| c.is_whitespace() || "-–:,".contains(c))
+            .to_string();
+        if !product.is_empty() {
+            confidence += 0.1;
+        }
+
+        for m in SHIPPING.find_iter(&title) {
+            let note = collapse_whitespace(m.as_str());
+            if !shipping_notes
+                .iter()
+                .any(|n| n.to_lowercase().contains(&note.to_lowercase()))
+            {
+                shipping_notes.push(note);
+            }
+        }
+
+        let coupon_codes = find_coupon_codes(&title);
+
+        let currency = if title.contains("USD") || title.contains("US$") {
+            Currency::Usd
+        } else {
+            Currency::Cad
+        };
+
+        Some(Deal {
+            category,
+            product,
+            price,
+            original_price,
+            currency,
+            retailer,
+            shipping_notes,
+            coupon_codes,
+            flair: None,
+            url: String::new(),
+            confidence,
+        })
+    }
+}
+
+/// Codes following `code`, `coupon` or `promo`. Plain words like "coupon for" don't count.
+pub(crate) fn find_coupon_codes(text: &str) -> Vec<String> {
+    COUPON
+        .captures_iter(text)
+        .map(|c| c[1].to_string())
+        .filter(|code| looks_like_code(code))
+        .collect()
+}
+
+pub(crate) fn looks_like_code(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit()) || token.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// Whether a bracketed group holds a price like `$232.37`, `119-50=69` or `$559/$659 NO GST`.
+fn is_price_group(text: &str) -> bool {
+    AMOUNT.is_match(text) && (text.contains('$') || BARE_PRICE_MATH.is_match(text))
+}
+
+/// The final price, and the original price for `before - discount = after` groups.
+fn parse_price_group(text: &str) -> (Option<f64>, Option<f64>) {
+    let amounts: Vec<f64> = AMOUNT
+        .find_iter(text)
+        .filter_map(|m| parse_amount(m.as_str()))
+        .collect();
+    match text.rfind('=') {
+        Some(eq) => {
+            let after = AMOUNT
+                .find(&text[eq..])
+                .and_then(|m| parse_amount(m.as_str()));
+            (
+                after,
+                amounts.first().copied().filter(|_| amounts.len() > 1),
+            )
+        }
+        None => (amounts.first().copied(), None),
+    }
+}
+
+/// Words left in a price group once the amounts are taken out, e.g. `+tax`.
+fn leftover_note(text: &str) -> Option<String> {
+    let stripped = AMOUNT.replace_all(text, "").replace('$', "");
+    let note = collapse_whitespace(&stripped)
+        .trim_matches(|c: char| c.is_whitespace() || "-=/,.".contains(c))
+        .to_string();
+    note.chars().any(|c| c.is_alphabetic()).then_some(note)
+}
+
+fn parse_amount(text: &str) -> Option<f64> {
+    text.replace(',', "").parse().ok()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Titles fetched without `raw_json=1` still have HTML entities escaped.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn parses_example_listing() -> eyre::Result<()> {
+        let links = example_links()?;
+        let deal = |prefix: &str| {
+            let link = links.iter().find(|l| l.title.starts_with(prefix)).unwrap();
+            Deal::from_link(link)
+        };
+
+        // Discussion threads and the like aren't deals
+        for link in links.iter().filter(|l| l.is_self) {
+            assert_eq!(Deal::from_link(link), None, "{}", link.title);
+        }
+
+        let gpu = deal("[GPU] ASRock").unwrap();
+        assert_eq!(gpu.category.as_deref(), Some("GPU"));
+        assert_eq!(gpu.product, "ASRock Radeon RX 6600 Challenger");
+        assert_eq!(gpu.price, Some(270.0));
+        assert_eq!(gpu.original_price, Some(600.0));
+        assert_eq!(gpu.retailer.as_deref(), Some("Newegg Canada"));
+        assert_eq!(gpu.currency, Currency::Cad);
+        assert!(gpu.confidence >= 0.99);
+
+        let untagged = deal("AMD Ryzen 7 7700").unwrap();
+        assert_eq!(untagged.category, None);
+        assert_eq!(untagged.product, "AMD Ryzen 7 7700");
+        assert_eq!(untagged.price, Some(232.37));
+        assert_eq!(untagged.retailer.as_deref(), Some("AliExpress"));
+        assert!(untagged.confidence < gpu.confidence);
+
+        let steam_deck = deal("[Handheld PC]").unwrap();
+        assert_eq!(steam_deck.price, Some(559.0));
+        assert_eq!(steam_deck.shipping_notes, vec!["NO GST"]);
+
+        let warehouse = deal("[CPU] Intel").unwrap();
+        assert_eq!(warehouse.price, Some(81.23));
+        assert_eq!(warehouse.shipping_notes, vec!["+tax"]);
+        assert_eq!(warehouse.retailer.as_deref(), Some("Amazon warehouse"));
+
+        let case = deal("[CASE+PSU]").unwrap();
+        assert!(case.product.contains("&"));
+        assert_eq!(case.price, Some(199.99));
+        assert_eq!(case.original_price, Some(300.0));
+
+        let coupon = deal("[CPU] AMD Ryzen 5 9600X").unwrap();
+        assert_eq!(coupon.product, "AMD Ryzen 5 9600X");
+        assert_eq!(coupon.price, Some(295.0));
+        assert_eq!(coupon.coupon_codes, vec!["25LD20"]);
+        assert_eq!(coupon.retailer.as_deref(), Some("amdglobal.aliexpress.com"));
+
+        assert_eq!(gpu.retailer_kind(), Some(Retailer::Newegg));
+        assert_eq!(coupon.retailer_kind(), Some(Retailer::AliExpress));
+
+        let walk_in = deal("9800X3D available Canada Computers St").unwrap();
+        assert_eq!(walk_in.price, Some(689.0));
+
+        // Every tagged title parses to something with a product
+        let tagged: Vec<Deal> = links
+            .iter()
+            .filter(|l| l.title.starts_with('['))
+            .map(|l| Deal::from_link(l).unwrap())
+            .collect();
+        assert!(tagged.len() >= 15);
+        assert!(tagged.iter().all(|d| !d.product.is_empty()));
+        assert!(tagged.iter().filter(|d| d.price.is_some()).count() >= tagged.len() - 1);
+        Ok(())
+    }
+}
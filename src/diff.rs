@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::comment_tree::CommentTree;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+
+/// What Reddit shows in place of the author or text of deleted content.
+pub const DELETED: &str = "[deleted]";
+/// What Reddit shows in place of the text of removed content.
+pub const REMOVED: &str = "[removed]";
+
+/// Something that differs between two snapshots of the same post or comment.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Fullname of the changed thing, e.g. `t3_1iambwd` or `t1_m9b3x4c`.
+    pub name: String,
+    pub kind: ChangeKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChangeKind {
+    TitleEdited {
+        before: String,
+        after: String,
+    },
+    /// The post's `selftext` or the comment's `body` changed.
+    TextEdited {
+        before: String,
+        after: String,
+    },
+    FlairChanged {
+        before: Option<String>,
+        after: Option<String>,
+    },
+    ScoreChanged {
+        before: i64,
+        after: i64,
+    },
+    /// The author's account was deleted, or they deleted the content.
+    AuthorDeleted {
+        author: String,
+    },
+    /// The content was deleted by its author.
+    Deleted,
+    /// The content was taken down by moderators, admins or a filter.
+    Removed {
+        category: Option<String>,
+    },
+    /// A comment present in the later snapshot only.
+    CommentAdded,
+    /// A comment present in the earlier snapshot only, e.g. pushed behind "load more".
+    CommentMissing,
+    /// The comment's replies in the later snapshot failed to parse. Comments
+    /// under it are not reported as missing, since they may still be there.
+    RepliesUnreadable {
+        error: String,
+    },
+}
+
+impl ChangeKind {
+    pub fn score_delta(&self) -> Option<i64> {
+        match self {
+            ChangeKind::ScoreChanged { before, after } => Some(after - before),
+            _ => None,
+        }
+    }
+}
+
+/// Compare two fetches of the same post.
+pub fn diff_links(before: &RedditLink, after: &RedditLink) -> Vec<Change> {
+    let mut kinds = Vec::new();
+    if before.title != after.title {
+        kinds.push(ChangeKind::TitleEdited {
+            before: before.title.clone(),
+            after: after.title.clone(),
+        });
+    }
+    if before.link_flair_text != after.link_flair_text {
+        kinds.push(ChangeKind::FlairChanged {
+            before: before.link_flair_text.clone(),
+            after: after.link_flair_text.clone(),
+        });
+    }
+    if before.score != after.score {
+        kinds.push(ChangeKind::ScoreChanged {
+            before: before.score,
+            after: after.score,
+        });
+    }
+    if before.author != DELETED && after.author == DELETED {
+        kinds.push(ChangeKind::AuthorDeleted {
+            author: before.author.clone(),
+        });
+    }
+    match (&before.removed_by_category, &after.removed_by_category) {
+        (None, Some(category)) if is_self_deletion(category) => kinds.push(ChangeKind::Deleted),
+        (None, Some(category)) => kinds.push(ChangeKind::Removed {
+            category: Some(category.clone()),
+        }),
+        _ => text_change(&before.selftext, &after.selftext, &mut kinds),
+    }
+    with_name(&after.name, kinds)
+}
+
+/// Compare two fetches of the same comment, ignoring its replies.
+pub fn diff_comments(before: &RedditComment, after: &RedditComment) -> Vec<Change> {
+    let mut kinds = Vec::new();
+    if before.score != after.score {
+        kinds.push(ChangeKind::ScoreChanged {
+            before: before.score,
+            after: after.score,
+        });
+    }
+    if before.author != DELETED && after.author == DELETED {
+        kinds.push(ChangeKind::AuthorDeleted {
+            author: before.author.clone(),
+        });
+    }
+    text_change(&before.body, &after.body, &mut kinds);
+    with_name(&after.name, kinds)
+}
+
+/// Compare two fetches of the same post's comments, matching comments by fullname at any depth.
+pub fn diff_comment_trees(before: &CommentTree, after: &CommentTree) -> Vec<Change> {
+    let before_by_name: HashMap<&str, &RedditComment> =
+        before.iter().map(|c| (c.name.as_str(), c)).collect();
+    let unreadable: Vec<_> = after.unreadable().collect();
+    let mut changes: Vec<Change> = unreadable
+        .iter()
+        .map(|(comment, error)| Change {
+            name: comment.name.clone(),
+            kind: ChangeKind::RepliesUnreadable {
+                error: error.to_string(),
+            },
+        })
+        .collect();
+    let unreadable: HashSet<&str> = unreadable.iter().map(|(c, _)| c.name.as_str()).collect();
+    let mut matched = 0;
+    for comment in after.iter() {
+        match before_by_name.get(comment.name.as_str()) {
+            Some(old) => {
+                matched += 1;
+                changes.extend(diff_comments(old, comment));
+            }
+            None => changes.push(Change {
+                name: comment.name.clone(),
+                kind: ChangeKind::CommentAdded,
+            }),
+        }
+    }
+    if matched < before_by_name.len() {
+        let after_names: HashSet<&str> = after.iter().map(|c| c.name.as_str()).collect();
+        changes.extend(
+            before
+                .iter()
+                .filter(|c| !after_names.contains(c.name.as_str()))
+                .filter(|c| !under_unreadable(c, &before_by_name, &after_names, &unreadable))
+                .map(|c| Change {
+                    name: c.name.clone(),
+                    kind: ChangeKind::CommentMissing,
+                }),
+        );
+    }
+    changes
+}
+
+/// Whether the nearest ancestor of `comment` still in the later snapshot is one
+/// whose replies couldn't be parsed.
+fn under_unreadable(
+    comment: &RedditComment,
+    before_by_name: &HashMap<&str, &RedditComment>,
+    after_names: &HashSet<&str>,
+    unreadable: &HashSet<&str>,
+) -> bool {
+    let mut parent = comment.parent_id.as_str();
+    while let Some(ancestor) = before_by_name.get(parent) {
+        if after_names.contains(parent) {
+            return unreadable.contains(parent);
+        }
+        parent = ancestor.parent_id.as_str();
+    }
+    false
+}
+
+fn is_self_deletion(category: &str) -> bool {
+    matches!(category, "deleted" | "author")
+}
+
+fn text_change(before: &str, after: &str, kinds: &mut Vec<ChangeKind>) {
+    if before == after {
+        return;
+    }
+    kinds.push(match after {
+        DELETED => ChangeKind::Deleted,
+        REMOVED => ChangeKind::Removed { category: None },
+        _ => ChangeKind::TextEdited {
+            before: before.to_string(),
+            after: after.to_string(),
+        },
+    });
+}
+
+fn with_name(name: &str, kinds: Vec<ChangeKind>) -> Vec<Change> {
+    kinds
+        .into_iter()
+        .map(|kind| Change {
+            name: name.to_string(),
+            kind,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::parse_link_comments;
+    use crate::lazy::Lazy;
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    fn first_link() -> eyre::Result<RedditLink> {
+        example_links()?
+            .into_iter()
+            .find(|link| !link.stickied)
+            .ok_or_else(|| eyre::eyre!("no links"))
+    }
+
+    #[test]
+    fn link_edits_and_removal() -> eyre::Result<()> {
+        let before = first_link()?;
+        assert!(diff_links(&before, &first_link()?).is_empty());
+
+        let mut after = first_link()?;
+        after.title = format!("{} EXPIRED", before.title);
+        after.link_flair_text = Some("Expired :(".to_string());
+        after.score = before.score + 10;
+        after.removed_by_category = Some("moderator".to_string());
+        let kinds: Vec<_> = diff_links(&before, &after)
+            .into_iter()
+            .map(|c| c.kind)
+            .collect();
+        assert!(matches!(kinds[0], ChangeKind::TitleEdited { .. }));
+        assert!(matches!(kinds[1], ChangeKind::FlairChanged { .. }));
+        assert_eq!(kinds[2].score_delta(), Some(10));
+        assert_eq!(
+            kinds[3],
+            ChangeKind::Removed {
+                category: Some("moderator".to_string())
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn comment_tree_deletions() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let before = CommentTree::from(parse_link_comments(&text)?);
+        let mut after = CommentTree::from(parse_link_comments(&text)?);
+        assert!(diff_comment_trees(&before, &after).is_empty());
+
+        let deleted = after.comments[0].name.clone();
+        let author = after.comments[0].author.clone();
+        after.comments[0].author = DELETED.to_string();
+        after.comments[0].body = DELETED.to_string();
+        let removed = after.comments.pop().unwrap().name;
+
+        let changes = diff_comment_trees(&before, &after);
+        assert!(changes.contains(&Change {
+            name: deleted.clone(),
+            kind: ChangeKind::AuthorDeleted { author },
+        }));
+        assert!(changes.contains(&Change {
+            name: deleted,
+            kind: ChangeKind::Deleted,
+        }));
+        assert!(changes.contains(&Change {
+            name: removed,
+            kind: ChangeKind::CommentMissing,
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_replies_are_not_missing() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let before = CommentTree::from(parse_link_comments(&text)?);
+        let mut after = CommentTree::from(parse_link_comments(&text)?);
+        assert!(after.comments[0].reply_comments()?.count() > 0);
+        after.comments[0].replies = Some(Lazy::new(serde_json::json!({
+            "kind": "Listing",
+            "data": {}
+        })));
+
+        let changes = diff_comment_trees(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].name, after.comments[0].name);
+        assert!(matches!(
+            changes[0].kind,
+            ChangeKind::RepliesUnreadable { .. }
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::client::fetch_duplicates;
+use crate::deals::Deal;
+use crate::links::normalize_link;
+use crate::models::RedditLink;
+
+/// How alike two deal titles must be, from 0 to 1, to count as a repost.
+pub const TITLE_THRESHOLD: f64 = 0.8;
+
+/// Prices further apart than this fraction are different deals, even for the same product.
+const PRICE_TOLERANCE: f64 = 0.02;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// Reddit listed it under `/duplicates/{id}`.
+    RedditDuplicates,
+    Crosspost,
+    /// The outbound links are the same once normalized.
+    SameUrl,
+    /// The titles describe the same product at the same retailer and price.
+    SimilarTitle,
+}
+
+/// Posts of the same thing, with the one to show for them all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateCluster<'a> {
+    /// The original: the earliest post that isn't a crosspost.
+    pub canonical: &'a RedditLink,
+    /// Every post in the cluster, including the canonical one, oldest first.
+    pub members: Vec<&'a RedditLink>,
+    pub reasons: BTreeSet<DuplicateReason>,
+}
+
+impl DuplicateCluster<'_> {
+    pub fn is_duplicated(&self) -> bool {
+        self.members.len() > 1
+    }
+}
+
+/// Groups posts that are the same deal posted more than once.
+#[derive(Debug, Clone)]
+pub struct DuplicateFinder {
+    pub title_threshold: f64,
+    /// Pairs of fullnames Reddit reported as duplicates.
+    known: Vec<(String, String)>,
+}
+
+impl Default for DuplicateFinder {
+    fn default() -> Self {
+        Self {
+            title_threshold: TITLE_THRESHOLD,
+            known: vec![],
+        }
+    }
+}
+
+impl DuplicateFinder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `duplicates` were posted with the same URL as `link`.
+    pub fn add_known<'a>(&mut self, link: &str, duplicates: impl IntoIterator<Item = &'a str>) {
+        for duplicate in duplicates {
+            self.known.push((link.to_string(), duplicate.to_string()));
+        }
+    }
+
+    /// Ask Reddit for the duplicates of `link` and record them.
+    ///
+    /// Returns the duplicates, which may be in other subreddits.
+    pub async fn fetch_known(
+        &mut self,
+        client: &reqwest::Client,
+        link: &RedditLink,
+    ) -> eyre::Result<Vec<RedditLink>> {
+        let duplicates = fetch_duplicates(client, &link.id).await?;
+        self.add_known(&link.name, duplicates.iter().map(|d| d.name.as_str()));
+        Ok(duplicates)
+    }
+
+    /// Group `links` into clusters, largest first. Posts with no duplicates
+    /// get a cluster of their own.
+    pub fn cluster<'a>(&self, links: &'a [RedditLink]) -> Vec<DuplicateCluster<'a>> {
+        let mut sets = DisjointSets::default();
+        for link in links {
+            sets.find(&link.name);
+        }
+        let mut reasons: Vec<(String, DuplicateReason)> = Vec::new();
+        let mut join = |a: &str, b: &str, reason: DuplicateReason| {
+            sets.union(a, b);
+            reasons.push((a.to_string(), reason));
+        };
+
+        for (a, b) in &self.known {
+            join(a, b, DuplicateReason::RedditDuplicates);
+        }
+
+        // Crossposts join their parent, even when the parent isn't among `links`
+        for link in links {
+            let parents = link
+                .crosspost_parent
+                .iter()
+                .chain(link.crosspost_parent_list.iter().flatten().map(|p| &p.name));
+            for parent in parents {
+                join(&link.name, parent, DuplicateReason::Crosspost);
+            }
+        }
+
+        let mut by_url: HashMap<String, &str> = HashMap::new();
+        for link in links {
+            if let Some(normalized) = normalize_link(link) {
+                match by_url.get(&normalized.url) {
+                    Some(first) => join(first, &link.name, DuplicateReason::SameUrl),
+                    None => {
+                        by_url.insert(normalized.url, &link.name);
+                    }
+                }
+            }
+        }
+
+        let deals: Vec<(&RedditLink, Deal, HashSet<String>)> = links
+            .iter()
+            .filter_map(|link| {
+                let deal = Deal::from_link(link)?;
+                let words = title_words(&deal.product);
+                Some((link, deal, words))
+            })
+            .collect();
+        for (i, (a, deal_a, words_a)) in deals.iter().enumerate() {
+            for (b, deal_b, words_b) in &deals[i + 1..] {
+                if same_offer(deal_a, deal_b) && jaccard(words_a, words_b) >= self.title_threshold {
+                    join(&a.name, &b.name, DuplicateReason::SimilarTitle);
+                }
+            }
+        }
+
+        let mut groups: HashMap<String, Vec<&'a RedditLink>> = HashMap::new();
+        for link in links {
+            groups.entry(sets.find(&link.name)).or_default().push(link);
+        }
+        let mut group_reasons: HashMap<String, BTreeSet<DuplicateReason>> = HashMap::new();
+        for (name, reason) in reasons {
+            group_reasons
+                .entry(sets.find(&name))
+                .or_default()
+                .insert(reason);
+        }
+
+        let mut clusters: Vec<DuplicateCluster<'a>> = groups
+            .into_iter()
+            .map(|(root, mut members)| {
+                members.sort_by(|a, b| a.created_utc.total_cmp(&b.created_utc));
+                let canonical = members
+                    .iter()
+                    .find(|l| l.crosspost_parent.is_none())
+                    .unwrap_or(&members[0]);
+                DuplicateCluster {
+                    canonical,
+                    reasons: if members.len() > 1 {
+                        group_reasons.remove(&root).unwrap_or_default()
+                    } else {
+                        BTreeSet::new()
+                    },
+                    members,
+                }
+            })
+            .collect();
+        clusters.sort_by(|a, b| {
+            b.members
+                .len()
+                .cmp(&a.members.len())
+                .then(a.canonical.created_utc.total_cmp(&b.canonical.created_utc))
+        });
+        clusters
+    }
+}
+
+/// Whether two deals could be the same offer: no conflicting retailer or price.
+fn same_offer(a: &Deal, b: &Deal) -> bool {
+    if let (Some(ra), Some(rb)) = (a.retailer_kind(), b.retailer_kind()) {
+        if ra != rb {
+            return false;
+        }
+    }
+    if let (Some(pa), Some(pb)) = (a.price, b.price) {
+        if (pa - pb).abs() > PRICE_TOLERANCE * pa.max(pb) {
+            return false;
+        }
+    }
+    true
+}
+
+fn title_words(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Union-find over fullnames.
+#[derive(Default)]
+struct DisjointSets {
+    parent: HashMap<String, String>,
+}
+
+impl DisjointSets {
+    fn find(&mut self, name: &str) -> String {
+        let parent = self
+            .parent
+            .entry(name.to_string())
+            .or_insert_with(|| name.to_string())
+            .clone();
+        if parent == name {
+            return parent;
+        }
+        let root = self.find(&parent);
+        self.parent.insert(name.to_string(), root.clone());
+        root
+    }
+
+    fn union(&mut self, a: &str, b: &str) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent.insert(b, a);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn clusters_example_listing() -> eyre::Result<()> {
+        let mut links = example_links()?;
+        let total = links.len();
+
+        // A crosspost elsewhere and a repost with a reworded title
+        let gpu = links
+            .iter()
+            .position(|l| l.title.starts_with("[GPU] ASRock"))
+            .unwrap();
+        let mut crosspost = example_links()?.swap_remove(gpu);
+        crosspost.name = "t3_crosspost".to_string();
+        crosspost.subreddit = "buildapcsales".to_string();
+        crosspost.crosspost_parent = Some(links[gpu].name.clone());
+        crosspost.created_utc += 60.0;
+        let mut repost = example_links()?.swap_remove(gpu);
+        repost.name = "t3_repost".to_string();
+        repost.url = "https://www.newegg.ca/p/N82E16814930066?utm_source=reddit".to_string();
+        repost.url_overridden_by_dest = None;
+        repost.title = "[GPU] ASRock Radeon RX 6600 Challenger ($270) [Newegg]".to_string();
+        repost.created_utc -= 60.0;
+        links.push(crosspost);
+        links.push(repost);
+
+        let mut finder = DuplicateFinder::new();
+        finder.add_known("t3_repost", ["t3_elsewhere"]);
+        let clusters = finder.cluster(&links);
+        assert_eq!(
+            clusters.iter().map(|c| c.members.len()).sum::<usize>(),
+            total + 2
+        );
+
+        // Four posts link the same Canada Computers 9800X3D page
+        let x3d = &clusters[0];
+        assert_eq!(x3d.members.len(), 4);
+        assert_eq!(x3d.reasons, BTreeSet::from([DuplicateReason::SameUrl]));
+        assert!(x3d.members.iter().all(|l| l.url.contains("/264908/")));
+
+        let gpu = clusters
+            .iter()
+            .find(|c| c.members.iter().any(|l| l.name == "t3_crosspost"))
+            .unwrap();
+        assert_eq!(gpu.members.len(), 3);
+        assert_eq!(gpu.canonical.name, "t3_repost");
+        assert!(gpu.reasons.contains(&DuplicateReason::Crosspost));
+        assert!(gpu.reasons.contains(&DuplicateReason::SameUrl));
+        assert!(gpu.reasons.contains(&DuplicateReason::RedditDuplicates));
+
+        // Same product at another store or price is a different deal
+        assert!(clusters
+            .iter()
+            .filter(|c| c.members.iter().any(|l| l.title.contains("R7 9800X3D")))
+            .all(|c| c.members.len() == 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::comment_tree::CommentTree;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+
+/// Flair text, lowercased, that marks a deal as no longer available.
+pub const EXPIRED_FLAIRS: &[&str] = &["expired", "sold out", "oos", "dead", "ended"];
+
+/// Evidence adding up to this much marks a deal expired.
+const EXPIRED_THRESHOLD: f32 = 1.5;
+/// Evidence adding up to this much marks a deal suspect.
+const SUSPECT_THRESHOLD: f32 = 0.5;
+
+/// Words saying a deal is over. Matched whole, ignoring case.
+static EXPIRED_WORDS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(dead|expired|oos|out of stock|sold out|no longer (available|in stock|working)|price (went|is|has gone) back up|back up to|code (doesn'?t|does not|isn'?t|no longer) work\w*|not available anymore)\b",
+    )
+    .unwrap()
+});
+
+/// An [`EXPIRED_FLAIRS`] entry as a whole word, so "Extended" or "Boosted" don't count.
+static EXPIRED_FLAIR: LazyLock<Regex> = LazyLock::new(|| {
+    let flairs: Vec<String> = EXPIRED_FLAIRS.iter().map(|f| regex::escape(f)).collect();
+    Regex::new(&format!(r"(?i)\b({})\b", flairs.join("|"))).unwrap()
+});
+
+/// Expiry marked on a title, e.g. `- DEAD`, `[Expired]` or `EDIT: sold out`.
+/// Words alone aren't enough in a title, which might be for Red Dead Redemption.
+static MARKED_TITLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(?:^|[\[(|:-]\s*)(dead|expired|oos|out of stock|sold out|ended)\s*(?:$|[\])|:!-])",
+    )
+    .unwrap()
+});
+
+/// Words saying a deal is still on.
+static ACTIVE_WORDS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(still (works|working|live|available|in stock|on)|back in stock|worked for me|just (ordered|bought|got one)|in stock now)\b",
+    )
+    .unwrap()
+});
+
+/// Negations that flip the expired word right after them, e.g. "not dead".
+static NEGATED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(not|isn'?t|wasn'?t|never)\s+$").unwrap());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DealStatus {
+    Active,
+    /// Some sign the deal is over, but not enough to be sure.
+    Suspect,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Evidence {
+    pub source: EvidenceSource,
+    /// The words that matched.
+    pub matched: String,
+    /// Positive for signs the deal is over, negative for signs it's still on.
+    pub weight: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EvidenceSource {
+    Flair,
+    Title,
+    Selftext,
+    Comment { name: String, score: i64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub status: DealStatus,
+    pub evidence: Vec<Evidence>,
+}
+
+impl Classification {
+    /// Sum of the evidence weights, above zero when the deal looks over.
+    pub fn total_weight(&self) -> f32 {
+        self.evidence.iter().map(|e| e.weight).sum()
+    }
+}
+
+pub fn is_expired_flair(flair: Option<&str>) -> bool {
+    flair.is_some_and(|f| {
+        let f = f.to_lowercase();
+        !ACTIVE_WORDS.is_match(&f) && EXPIRED_FLAIR.is_match(&f)
+    })
+}
+
+/// Decide whether a deal post is still worth acting on.
+///
+/// An expired flair settles it. Otherwise evidence from the title, selftext
+/// and comments is weighed up, comments counting more the higher they score.
+/// Comments saying the deal is still on are ignored if a later one says it's
+/// over, and reports of it being over count for half if a later one says
+/// it's still on.
+pub fn classify(link: &RedditLink, comments: Option<&CommentTree>) -> Classification {
+    let mut evidence = Vec::new();
+    if let Some(flair) = &link.link_flair_text {
+        if is_expired_flair(Some(flair)) {
+            evidence.push(Evidence {
+                source: EvidenceSource::Flair,
+                matched: flair.clone(),
+                weight: EXPIRED_THRESHOLD,
+            });
+            return Classification {
+                status: DealStatus::Expired,
+                evidence,
+            };
+        }
+        if let Some(m) = ACTIVE_WORDS.find(flair) {
+            evidence.push(Evidence {
+                source: EvidenceSource::Flair,
+                matched: m.as_str().to_string(),
+                weight: -1.0,
+            });
+        }
+    }
+    // Posters mark the title or add "EDIT: sold out" to the text
+    if let Some(c) = MARKED_TITLE.captures(&link.title) {
+        evidence.push(Evidence {
+            source: EvidenceSource::Title,
+            matched: c[1].to_string(),
+            weight: EXPIRED_THRESHOLD,
+        });
+    }
+    if let Some(matched) = expired_match(&link.selftext) {
+        evidence.push(Evidence {
+            source: EvidenceSource::Selftext,
+            matched,
+            weight: 1.0,
+        });
+    }
+
+    if let Some(tree) = comments {
+        let mut found: Vec<(&RedditComment, Evidence)> = tree
+            .iter()
+            .filter_map(|comment| Some((comment, comment_evidence(comment)?)))
+            .collect();
+        let latest = |expired: bool| {
+            found
+                .iter()
+                .filter(|(_, e)| (e.weight > 0.0) == expired)
+                .map(|(c, _)| c.created_utc)
+                .fold(f64::MIN, f64::max)
+        };
+        let (latest_expired, latest_active) = (latest(true), latest(false));
+        // "Worked for me yesterday" says nothing about a later "dead now"
+        found.retain(|(c, e)| e.weight > 0.0 || c.created_utc >= latest_expired);
+        for (c, e) in &mut found {
+            if e.weight > 0.0 && c.created_utc < latest_active {
+                e.weight /= 2.0;
+            }
+        }
+        evidence.extend(found.into_iter().map(|(_, e)| e));
+    }
+
+    let total: f32 = evidence.iter().map(|e| e.weight).sum();
+    let status = if total >= EXPIRED_THRESHOLD {
+        DealStatus::Expired
+    } else if total >= SUSPECT_THRESHOLD {
+        DealStatus::Suspect
+    } else {
+        DealStatus::Active
+    };
+    Classification { status, evidence }
+}
+
+fn comment_evidence(comment: &RedditComment) -> Option<Evidence> {
+    // A comment with a few upvotes speaks for the people who agreed with it
+    let weight = 0.4 * (1.0 + (comment.score.max(0) as f32).ln_1p());
+    let source = EvidenceSource::Comment {
+        name: comment.name.clone(),
+        score: comment.score,
+    };
+    if let Some(m) = ACTIVE_WORDS.find(&comment.body) {
+        return Some(Evidence {
+            source,
+            matched: m.as_str().to_string(),
+            weight: -weight,
+        });
+    }
+    expired_match(&comment.body).map(|matched| Evidence {
+        source,
+        matched,
+        weight,
+    })
+}
+
+fn expired_match(text: &str) -> Option<String> {
+    EXPIRED_WORDS
+        .find_iter(text)
+        .find(|m| !NEGATED.is_match(&text[..m.start()]))
+        .map(|m| m.as_str().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::parse_link_comments;
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn flair_and_title() -> eyre::Result<()> {
+        for link in example_links()? {
+            let status = classify(&link, None).status;
+            match link.link_flair_text.as_deref() {
+                Some("Expired" | "Sold Out") => assert_eq!(status, DealStatus::Expired),
+                Some("back in stock") => assert_eq!(status, DealStatus::Active),
+                _ => {}
+            }
+        }
+
+        let mut link = example_links()?.remove(2);
+        assert_eq!(classify(&link, None).status, DealStatus::Active);
+        link.title = format!("{} - DEAD", link.title);
+        let classification = classify(&link, None);
+        assert_eq!(classification.status, DealStatus::Expired);
+        assert_eq!(classification.evidence[0].source, EvidenceSource::Title);
+        link.title = "[GPU] Not dead yet ($300) [CC]".to_string();
+        assert_eq!(classify(&link, None).status, DealStatus::Active);
+        link.title = "[GAME] Red Dead Redemption 2 ($20) [Steam]".to_string();
+        assert_eq!(classify(&link, None).status, DealStatus::Active);
+        link.title = "[Expired] [GAME] Dead Space ($15) [Steam]".to_string();
+        assert_eq!(classify(&link, None).status, DealStatus::Expired);
+
+        for flair in ["Recommended", "Extended", "Boosted", "Price Drop"] {
+            assert!(!is_expired_flair(Some(flair)), "{flair}");
+        }
+        assert!(is_expired_flair(Some("OOS")));
+        Ok(())
+    }
+
+    #[test]
+    fn weighs_comments() -> eyre::Result<()> {
+        let link = example_links()?.remove(2);
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let mut tree = CommentTree::from(parse_link_comments(&text)?);
+        assert_eq!(classify(&link, Some(&tree)).status, DealStatus::Active);
+
+        let latest = tree.iter().map(|c| c.created_utc).fold(0.0, f64::max);
+        tree.comments[0].body = "Price went back up to $280".to_string();
+        tree.comments[0].score = 1;
+        tree.comments[0].created_utc = latest + 60.0;
+        assert_eq!(classify(&link, Some(&tree)).status, DealStatus::Suspect);
+
+        tree.comments[1].body = "OOS now, shows sold out for me too".to_string();
+        tree.comments[1].score = 12;
+        tree.comments[1].created_utc = latest + 120.0;
+        let classification = classify(&link, Some(&tree));
+        assert_eq!(classification.status, DealStatus::Expired);
+        assert!(classification.evidence.iter().any(|e| e.matched == "OOS"));
+
+        // A later "still works" outweighs them
+        tree.comments[2].body = "Still works, just ordered".to_string();
+        tree.comments[2].score = 3;
+        tree.comments[2].created_utc = latest + 180.0;
+        assert_eq!(classify(&link, Some(&tree)).status, DealStatus::Active);
+        Ok(())
+    }
+}
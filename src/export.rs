@@ -0,0 +1,497 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parquet::basic::ConvertedType;
+use parquet::basic::Repetition;
+use parquet::basic::Type as PhysicalType;
+use parquet::data_type::BoolType;
+use parquet::data_type::ByteArray;
+use parquet::data_type::ByteArrayType;
+use parquet::data_type::DoubleType;
+use parquet::data_type::Int64Type;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type as SchemaType;
+use serde_json::json;
+use tracing::warn;
+
+use crate::client::cache_dir;
+use crate::client::parse_link_comments;
+use crate::client::ListingSort;
+use crate::client::SubredditSlug;
+use crate::comment_tree::CommentTree;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+use crate::models::RedditResponse;
+use crate::models::RedditThing;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Text,
+    OptionalText,
+    Int,
+    Float,
+    OptionalFloat,
+    Bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field<'a> {
+    Text(Option<&'a str>),
+    Int(i64),
+    Float(Option<f64>),
+    Bool(bool),
+}
+
+/// A row type that can be exported.
+pub trait Record {
+    /// Column names and types in output order. This is the schema consumers
+    /// see, so add new columns at the end rather than reordering.
+    const COLUMNS: &'static [(&'static str, ColumnType)];
+
+    /// The value of the column at `index` in [`Record::COLUMNS`].
+    fn field(&self, index: usize) -> Field<'_>;
+}
+
+impl Record for RedditLink {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("id", ColumnType::Text),
+        ("name", ColumnType::Text),
+        ("subreddit", ColumnType::Text),
+        ("author", ColumnType::Text),
+        ("title", ColumnType::Text),
+        ("selftext", ColumnType::Text),
+        ("url", ColumnType::Text),
+        ("domain", ColumnType::Text),
+        ("permalink", ColumnType::Text),
+        ("link_flair_text", ColumnType::OptionalText),
+        ("score", ColumnType::Int),
+        ("upvote_ratio", ColumnType::Float),
+        ("num_comments", ColumnType::Int),
+        ("over_18", ColumnType::Bool),
+        ("stickied", ColumnType::Bool),
+        ("removed_by_category", ColumnType::OptionalText),
+        ("created_utc", ColumnType::Float),
+        ("edited_utc", ColumnType::OptionalFloat),
+    ];
+
+    fn field(&self, index: usize) -> Field<'_> {
+        match index {
+            0 => Field::Text(Some(&self.id)),
+            1 => Field::Text(Some(&self.name)),
+            2 => Field::Text(Some(&self.subreddit)),
+            3 => Field::Text(Some(&self.author)),
+            4 => Field::Text(Some(&self.title)),
+            5 => Field::Text(Some(&self.selftext)),
+            6 => Field::Text(Some(&self.url)),
+            7 => Field::Text(Some(&self.domain)),
+            8 => Field::Text(Some(&self.permalink)),
+            9 => Field::Text(self.link_flair_text.as_deref()),
+            10 => Field::Int(self.score),
+            11 => Field::Float(Some(self.upvote_ratio)),
+            12 => Field::Int(self.num_comments),
+            13 => Field::Bool(self.over_18),
+            14 => Field::Bool(self.stickied),
+            15 => Field::Text(self.removed_by_category.as_deref()),
+            16 => Field::Float(Some(self.created_utc)),
+            17 => Field::Float(self.edited),
+            _ => panic!("RedditLink has no column {index}"),
+        }
+    }
+}
+
+/// A comment flattened out of its tree; `parent_id` and `depth` keep its place.
+impl Record for RedditComment {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("id", ColumnType::Text),
+        ("name", ColumnType::Text),
+        ("link_id", ColumnType::Text),
+        ("parent_id", ColumnType::Text),
+        ("depth", ColumnType::Int),
+        ("subreddit", ColumnType::Text),
+        ("author", ColumnType::Text),
+        ("body", ColumnType::Text),
+        ("score", ColumnType::Int),
+        ("controversiality", ColumnType::Int),
+        ("is_submitter", ColumnType::Bool),
+        ("stickied", ColumnType::Bool),
+        ("created_utc", ColumnType::Float),
+        ("edited_utc", ColumnType::OptionalFloat),
+    ];
+
+    fn field(&self, index: usize) -> Field<'_> {
+        match index {
+            0 => Field::Text(Some(&self.id)),
+            1 => Field::Text(Some(&self.name)),
+            2 => Field::Text(Some(&self.link_id)),
+            3 => Field::Text(Some(&self.parent_id)),
+            4 => Field::Int(self.depth),
+            5 => Field::Text(Some(&self.subreddit)),
+            6 => Field::Text(Some(&self.author)),
+            7 => Field::Text(Some(&self.body)),
+            8 => Field::Int(self.score),
+            9 => Field::Int(self.controversiality),
+            10 => Field::Bool(self.is_submitter),
+            11 => Field::Bool(self.stickied),
+            12 => Field::Float(Some(self.created_utc)),
+            13 => Field::Float(self.edited),
+            _ => panic!("RedditComment has no column {index}"),
+        }
+    }
+}
+
+/// Every comment in every tree, parents before their replies.
+pub fn flatten_comments(trees: &[CommentTree]) -> Vec<&RedditComment> {
+    trees.iter().flat_map(|tree| tree.iter()).collect()
+}
+
+/// Links from every cached subreddit page, or only `subreddit`'s, deduplicated by id.
+///
+/// Files that fail to parse are logged and skipped.
+pub async fn cached_links(subreddit: Option<&SubredditSlug>) -> eyre::Result<Vec<RedditLink>> {
+    let mut seen = HashSet::new();
+    let mut links = Vec::new();
+    for (path, text) in read_cache_dir(&cache_dir().join("subreddit")).await? {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if subreddit.is_some_and(|s| !is_cached_page_of(&file_name, s)) {
+            continue;
+        }
+        let listing = match serde_json::from_str::<RedditResponse>(&text) {
+            Ok(RedditResponse::Listing(listing)) => listing,
+            Err(e) => {
+                warn!("Skipping unreadable cache file {}: {e}", path.display());
+                continue;
+            }
+        };
+        for thing in listing.children {
+            if let RedditThing::Link(link) = thing {
+                if seen.insert(link.id.clone()) {
+                    links.push(link);
+                }
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Whether a file in the subreddit cache is one of `subreddit`'s pages.
+///
+/// Pages are cached as `{subreddit}_{page}.json`, or as
+/// `{subreddit}_{sort}_{page}.json` in orders other than hot.
+fn is_cached_page_of(file_name: &str, subreddit: &SubredditSlug) -> bool {
+    let Some(rest) = file_name
+        .strip_prefix(subreddit.as_ref())
+        .and_then(|rest| rest.strip_prefix('_'))
+        .and_then(|rest| rest.strip_suffix(".json"))
+    else {
+        return false;
+    };
+    let page = match rest.split_once('_') {
+        Some((sort, page)) if ListingSort::ALL.iter().any(|s| s.as_str() == sort) => page,
+        Some(_) => return false,
+        None => rest,
+    };
+    page.parse::<usize>().is_ok()
+}
+
+/// Comment trees from every cached post, keyed by post id.
+///
+/// Files that fail to parse are logged and skipped.
+pub async fn cached_comment_trees() -> eyre::Result<Vec<(String, CommentTree)>> {
+    let mut trees = Vec::new();
+    for (path, text) in read_cache_dir(&cache_dir().join("posts")).await? {
+        let Some(id) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        match parse_link_comments(&text) {
+            Ok(comments) => trees.push((id, CommentTree::from(comments))),
+            Err(e) => warn!("Skipping unreadable cache file {}: {e}", path.display()),
+        }
+    }
+    Ok(trees)
+}
+
+/// The `.json` files in `dir`, sorted by path. A missing directory is empty.
+async fn read_cache_dir(dir: &Path) -> eyre::Result<Vec<(PathBuf, String)>> {
+    if !tokio::fs::try_exists(dir).await.unwrap_or(false) {
+        return Ok(vec![]);
+    }
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let text = tokio::fs::read_to_string(&path).await?;
+        files.push((path, text));
+    }
+    Ok(files)
+}
+
+/// Resolve column names to indices into [`Record::COLUMNS`]; no names means every column.
+pub fn select_columns<R: Record>(names: &[&str]) -> eyre::Result<Vec<usize>> {
+    if names.is_empty() {
+        return Ok((0..R::COLUMNS.len()).collect());
+    }
+    names
+        .iter()
+        .map(|name| {
+            R::COLUMNS
+                .iter()
+                .position(|(column, _)| column == name)
+                .ok_or_else(|| {
+                    eyre::eyre!(
+                        "Unknown column {name:?}, expected one of {}",
+                        R::COLUMNS
+                            .iter()
+                            .map(|(c, _)| *c)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Write one JSON object per line holding the chosen `columns`.
+pub fn write_ndjson<'a, R: Record + 'a>(
+    mut writer: impl Write,
+    records: impl IntoIterator<Item = &'a R>,
+    columns: &[&str],
+) -> eyre::Result<usize> {
+    let indices = select_columns::<R>(columns)?;
+    let mut count = 0;
+    for record in records {
+        let object: serde_json::Map<String, serde_json::Value> = indices
+            .iter()
+            .map(|&i| {
+                let value = match record.field(i) {
+                    Field::Text(v) => json!(v),
+                    Field::Int(v) => json!(v),
+                    Field::Float(v) => json!(v),
+                    Field::Bool(v) => json!(v),
+                };
+                (R::COLUMNS[i].0.to_string(), value)
+            })
+            .collect();
+        serde_json::to_writer(&mut writer, &object)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Write a CSV with a header row and the chosen `columns`. Missing values are empty cells.
+pub fn write_csv<'a, R: Record + 'a>(
+    writer: impl Write,
+    records: impl IntoIterator<Item = &'a R>,
+    columns: &[&str],
+) -> eyre::Result<usize> {
+    let indices = select_columns::<R>(columns)?;
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(indices.iter().map(|&i| R::COLUMNS[i].0))?;
+    let mut count = 0;
+    for record in records {
+        csv.write_record(indices.iter().map(|&i| match record.field(i) {
+            Field::Text(v) => v.unwrap_or_default().to_string(),
+            Field::Int(v) => v.to_string(),
+            Field::Float(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+            Field::Bool(v) => v.to_string(),
+        }))?;
+        count += 1;
+    }
+    csv.flush()?;
+    Ok(count)
+}
+
+/// The Parquet schema for a record type, with every column from [`Record::COLUMNS`].
+pub fn parquet_schema<R: Record>(name: &str) -> eyre::Result<SchemaType> {
+    let fields = R::COLUMNS
+        .iter()
+        .map(|&(column, ty)| {
+            let (physical, repetition) = match ty {
+                ColumnType::Text => (PhysicalType::BYTE_ARRAY, Repetition::REQUIRED),
+                ColumnType::OptionalText => (PhysicalType::BYTE_ARRAY, Repetition::OPTIONAL),
+                ColumnType::Int => (PhysicalType::INT64, Repetition::REQUIRED),
+                ColumnType::Float => (PhysicalType::DOUBLE, Repetition::REQUIRED),
+                ColumnType::OptionalFloat => (PhysicalType::DOUBLE, Repetition::OPTIONAL),
+                ColumnType::Bool => (PhysicalType::BOOLEAN, Repetition::REQUIRED),
+            };
+            let mut builder =
+                SchemaType::primitive_type_builder(column, physical).with_repetition(repetition);
+            if physical == PhysicalType::BYTE_ARRAY {
+                builder = builder.with_converted_type(ConvertedType::UTF8);
+            }
+            Ok(Arc::new(builder.build()?))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    Ok(SchemaType::group_type_builder(name)
+        .with_fields(fields)
+        .build()?)
+}
+
+/// Write every column of `records` as a single Parquet row group.
+pub fn write_parquet<R: Record>(
+    writer: impl Write + Send,
+    records: &[&R],
+    schema_name: &str,
+) -> eyre::Result<()> {
+    let schema = Arc::new(parquet_schema::<R>(schema_name)?);
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut file = SerializedFileWriter::new(writer, schema, props)?;
+    let mut row_group = file.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        let fields = records.iter().map(|r| r.field(index));
+        // Optional columns get a definition level per row: 1 if present, 0 if null
+        let optional = matches!(
+            R::COLUMNS[index].1,
+            ColumnType::OptionalText | ColumnType::OptionalFloat
+        );
+        match R::COLUMNS[index].1 {
+            ColumnType::Text | ColumnType::OptionalText => {
+                let values: Vec<Option<ByteArray>> = fields
+                    .map(|f| match f {
+                        Field::Text(v) => v.map(|s| ByteArray::from(s.as_bytes().to_vec())),
+                        _ => None,
+                    })
+                    .collect();
+                write_column::<ByteArrayType>(column.typed(), values, optional)?;
+            }
+            ColumnType::Int => {
+                let values = fields
+                    .map(|f| match f {
+                        Field::Int(v) => Some(v),
+                        _ => None,
+                    })
+                    .collect();
+                write_column::<Int64Type>(column.typed(), values, optional)?;
+            }
+            ColumnType::Float | ColumnType::OptionalFloat => {
+                let values = fields
+                    .map(|f| match f {
+                        Field::Float(v) => v,
+                        _ => None,
+                    })
+                    .collect();
+                write_column::<DoubleType>(column.typed(), values, optional)?;
+            }
+            ColumnType::Bool => {
+                let values = fields
+                    .map(|f| match f {
+                        Field::Bool(v) => Some(v),
+                        _ => None,
+                    })
+                    .collect();
+                write_column::<BoolType>(column.typed(), values, optional)?;
+            }
+        }
+        column.close()?;
+        index += 1;
+    }
+    row_group.close()?;
+    file.close()?;
+    Ok(())
+}
+
+fn write_column<T: parquet::data_type::DataType>(
+    writer: &mut parquet::column::writer::ColumnWriterImpl<'_, T>,
+    values: Vec<Option<T::T>>,
+    optional: bool,
+) -> eyre::Result<()> {
+    let def_levels: Vec<i16> = values.iter().map(|v| v.is_some() as i16).collect();
+    let present: Vec<T::T> = values.into_iter().flatten().collect();
+    if optional {
+        writer.write_batch(&present, Some(&def_levels), None)?;
+    } else {
+        eyre::ensure!(
+            present.len() == def_levels.len(),
+            "Required column has a missing value"
+        );
+        writer.write_batch(&present, None, None)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::example_links;
+    use crate::test_support::temp_dir;
+    use parquet::file::reader::FileReader;
+    use parquet::file::reader::SerializedFileReader;
+
+    use super::*;
+
+    #[test]
+    fn ndjson_and_csv_use_chosen_columns() -> eyre::Result<()> {
+        let links = example_links()?;
+
+        let mut ndjson = Vec::new();
+        let written = write_ndjson(&mut ndjson, &links, &["id", "score", "link_flair_text"])?;
+        assert_eq!(written, links.len());
+        let first: serde_json::Value =
+            serde_json::from_str(String::from_utf8(ndjson)?.lines().next().unwrap())?;
+        assert_eq!(first["id"], links[0].id.as_str());
+        assert_eq!(first.as_object().unwrap().len(), 3);
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &links, &["title", "created_utc"])?;
+        let csv = String::from_utf8(csv)?;
+        assert_eq!(csv.lines().next(), Some("title,created_utc"));
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        assert_eq!(reader.records().count(), links.len());
+
+        assert!(write_csv(Vec::new(), &links, &["nope"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cached_page_names() {
+        let sub = SubredditSlug::new("bapcsalescanada");
+        assert!(is_cached_page_of("bapcsalescanada_0.json", &sub));
+        assert!(is_cached_page_of("bapcsalescanada_top_2.json", &sub));
+        assert!(is_cached_page_of(
+            "bapcsalescanada_controversial_0.json",
+            &sub
+        ));
+        assert!(!is_cached_page_of("bapcsalescanada_best_0.json", &sub));
+        assert!(!is_cached_page_of("bapcsalescanada_top.json", &sub));
+        assert!(!is_cached_page_of("bapcsalescanadaextra_0.json", &sub));
+        assert!(!is_cached_page_of(
+            "bapcsales_0.json",
+            &SubredditSlug::new("bapc")
+        ));
+    }
+
+    #[test]
+    fn parquet_has_one_row_per_comment() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let trees = vec![CommentTree::from(parse_link_comments(&text)?)];
+        let comments = flatten_comments(&trees);
+        assert!(comments.iter().any(|c| c.depth > 0));
+
+        let path = temp_dir("export").join("comments.parquet");
+        write_parquet(std::fs::File::create(&path)?, &comments, "comments")?;
+        let reader = SerializedFileReader::new(std::fs::File::open(&path)?)?;
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows() as usize, comments.len());
+        let columns: Vec<&str> = metadata
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|c| c.name())
+            .collect();
+        let expected: Vec<&str> = RedditComment::COLUMNS.iter().map(|(c, _)| *c).collect();
+        assert_eq!(columns, expected);
+        Ok(())
+    }
+}
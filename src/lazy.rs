@@ -1,62 +1,142 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use serde::de::DeserializeOwned;
 use serde::de::Deserializer;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::value::RawValue;
 use serde_json::Value;
 
 use crate::models::RedditResponse;
 
-#[derive(Debug, PartialEq)]
-pub enum LazyResponse {
-    Raw(Value), // store the raw object
-    Parsed(RedditResponse),
-}
-
-impl LazyResponse {
-    pub fn get(&mut self) -> eyre::Result<&RedditResponse> {
-        match self {
-            LazyResponse::Parsed(r) => {
-                // Already parsed, just return the reference
-                Ok(r)
-            }
-            LazyResponse::Raw(v) => {
-                // 1) Take the raw Value out of `v`
-                let value = std::mem::take(v);
-
-                // 2) Convert it to a RedditResponse
-                let response: RedditResponse = serde_json::from_value(value)?;
-
-                // 3) Store that newly parsed response in `self`
-                *self = LazyResponse::Parsed(response);
-
-                // 4) Now match again on `self` so we can reference the newly
-                //    stored data, which lives as long as `self` does.
-                match self {
-                    LazyResponse::Parsed(r) => Ok(r),
-                    LazyResponse::Raw(_) => unreachable!("We just set it to Parsed"),
-                }
-            }
+pub type LazyResponse = Lazy<RedditResponse>;
+
+/// A JSON value that is only parsed into `T` the first time it is asked for.
+///
+/// The unparsed JSON text is held as a [`RawValue`], so deferring a field
+/// costs one copy of its text rather than a `serde_json::Value` tree. The
+/// text is kept after parsing so the original payload can be serialized back
+/// out unchanged. Parsing happens at most once, and a failed parse is
+/// remembered so every caller sees the same error.
+#[derive(Debug, Clone)]
+pub struct Lazy<T> {
+    raw: Box<RawValue>,
+    parsed: OnceLock<Result<T, LazyError>>,
+}
+
+impl<T> Lazy<T> {
+    pub fn new(raw: Value) -> Self {
+        Self::from_raw(serde_json::value::to_raw_value(&raw).expect("Value always serializes"))
+    }
+
+    pub fn from_raw(raw: Box<RawValue>) -> Self {
+        Self {
+            raw,
+            parsed: OnceLock::new(),
+        }
+    }
+
+    pub fn raw(&self) -> &RawValue {
+        &self.raw
+    }
+
+    pub fn is_parsed(&self) -> bool {
+        self.parsed.get().is_some()
+    }
+}
+
+impl<T: DeserializeOwned> Lazy<T> {
+    pub fn get(&self) -> Result<&T, LazyError> {
+        self.parsed
+            .get_or_init(|| {
+                serde_json::from_str(self.raw.get()).map_err(|e| LazyError(Arc::new(e)))
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    pub fn into_inner(self) -> Result<T, LazyError> {
+        match self.parsed.into_inner() {
+            Some(parsed) => parsed,
+            None => serde_json::from_str(self.raw.get()).map_err(|e| LazyError(Arc::new(e))),
         }
     }
 }
 
-impl Serialize for LazyResponse {
+/// Compares the raw JSON text, so formatting differences count as inequality.
+impl<T> PartialEq for Lazy<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw.get() == other.raw.get()
+    }
+}
+
+impl<T> Serialize for Lazy<T> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        match self {
-            LazyResponse::Raw(v) => v.serialize(serializer),
-            LazyResponse::Parsed(r) => r.serialize(serializer),
-        }
+        self.raw.serialize(serializer)
     }
 }
 
-impl<'de> Deserialize<'de> for LazyResponse {
+impl<'de, T> Deserialize<'de> for Lazy<T> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let val = Value::deserialize(deserializer)?;
-        Ok(LazyResponse::Raw(val))
+        let raw = Box::<RawValue>::deserialize(deserializer)?;
+        Ok(Lazy::from_raw(raw))
+    }
+}
+
+/// A cached parse failure, cheap to clone so it can be handed to every caller.
+#[derive(Debug, Clone)]
+pub struct LazyError(Arc<serde_json::Error>);
+
+impl fmt::Display for LazyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to parse lazy value: {}", self.0)
+    }
+}
+
+impl std::error::Error for LazyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parses_once_through_shared_ref() -> eyre::Result<()> {
+        let lazy: Arc<Lazy<Vec<i64>>> = Arc::new(Lazy::new(json!([1, 2, 3])));
+        assert!(!lazy.is_parsed());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let lazy = lazy.clone();
+                std::thread::spawn(move || lazy.get().map(|v| v.iter().sum::<i64>()).ok())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Some(6));
+        }
+        assert!(lazy.is_parsed());
+        assert_eq!(serde_json::to_value(&*lazy)?, json!([1, 2, 3]));
+        Ok(())
+    }
+
+    #[test]
+    fn caches_errors() {
+        let lazy: Lazy<Vec<i64>> = Lazy::new(json!({"not": "a list"}));
+        let first = lazy.get().unwrap_err().to_string();
+        let second = lazy.get().unwrap_err().to_string();
+        assert_eq!(first, second);
+        assert!(lazy.is_parsed());
     }
 }
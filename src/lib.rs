@@ -0,0 +1,26 @@
+pub mod alerts;
+pub mod backfill;
+pub mod client;
+pub mod comment_tree;
+pub mod crawl;
+pub mod deals;
+pub mod diff;
+pub mod duplicates;
+pub mod expiry;
+pub mod export;
+pub mod lazy;
+pub mod links;
+pub mod mentions;
+pub mod models;
+pub mod notify;
+pub mod persist;
+pub mod price_history;
+pub mod rate_limit;
+pub mod search;
+pub mod sentiment;
+pub mod storage;
+pub mod sync;
+#[cfg(test)]
+mod test_support;
+pub mod track;
+pub mod watch;
@@ -0,0 +1,527 @@
+use serde::Deserialize;
+use serde::Serialize;
+use url::Url;
+
+use crate::models::RedditLink;
+
+/// Redirects are unwrapped at most this many times, in case they loop.
+const MAX_REDIRECT_DEPTH: usize = 5;
+
+/// Query parameters that only track where a click came from, on any site.
+const TRACKING_PARAMS: &[&str] = &[
+    "cjevent",
+    "fbclid",
+    "gbraid",
+    "gclid",
+    "irclickid",
+    "irgwc",
+    "mc_cid",
+    "mc_eid",
+    "msclkid",
+    "srsltid",
+    "wbraid",
+];
+
+const TRACKING_PREFIXES: &[&str] = &["utm_"];
+
+/// Parameters that only track clicks on one retailer's site, and the
+/// prefixes of more. Elsewhere names like `ref`, `tag` or `sr` can matter.
+const RETAILER_TRACKING: &[(Retailer, &[&str], &[&str])] = &[
+    (
+        Retailer::Amazon,
+        &[
+            "_encoding",
+            "ascsubtag",
+            "camp",
+            "content-id",
+            "creative",
+            "creativeASIN",
+            "crid",
+            "dib",
+            "dib_tag",
+            "linkCode",
+            "linkId",
+            "psc",
+            "qid",
+            "ref",
+            "ref_",
+            "smid",
+            "sprefix",
+            "sr",
+            "tag",
+            "th",
+        ],
+        &["pd_rd_", "pf_rd_"],
+    ),
+    (Retailer::AliExpress, &["spm", "scm"], &["aff_"]),
+    (Retailer::BestBuy, &["icid"], &[]),
+    (Retailer::Newegg, &["cm_sp", "icid"], &[]),
+];
+
+/// Retailers deals are posted for, for grouping deals regardless of how the
+/// post spelled the name or which country site it linked to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Retailer {
+    Amazon,
+    Newegg,
+    BestBuy,
+    CanadaComputers,
+    MemoryExpress,
+    AliExpress,
+    Walmart,
+    Costco,
+    Staples,
+    TheSource,
+    Visions,
+    Steam,
+    /// Anything else, by domain without `www.`.
+    Other(String),
+}
+
+/// Needles matched against a squashed retailer name, most specific first.
+const RETAILER_NAMES: &[(&str, Retailer)] = &[
+    ("amazon", Retailer::Amazon),
+    ("amzn", Retailer::Amazon),
+    ("newegg", Retailer::Newegg),
+    ("bestbuy", Retailer::BestBuy),
+    ("canadacomputers", Retailer::CanadaComputers),
+    ("memoryexpress", Retailer::MemoryExpress),
+    ("memexpress", Retailer::MemoryExpress),
+    ("aliexpress", Retailer::AliExpress),
+    ("walmart", Retailer::Walmart),
+    ("costco", Retailer::Costco),
+    ("staples", Retailer::Staples),
+    ("thesource", Retailer::TheSource),
+    ("visions", Retailer::Visions),
+    ("steampowered", Retailer::Steam),
+    ("steam", Retailer::Steam),
+];
+
+/// Registrable names of retailer sites, without the public suffix, compared whole.
+const RETAILER_HOSTS: &[(&str, Retailer)] = &[
+    ("amazon", Retailer::Amazon),
+    ("amzn", Retailer::Amazon),
+    ("newegg", Retailer::Newegg),
+    ("bestbuy", Retailer::BestBuy),
+    ("canadacomputers", Retailer::CanadaComputers),
+    ("memoryexpress", Retailer::MemoryExpress),
+    ("aliexpress", Retailer::AliExpress),
+    ("walmart", Retailer::Walmart),
+    ("costco", Retailer::Costco),
+    ("staples", Retailer::Staples),
+    ("thesource", Retailer::TheSource),
+    ("visions", Retailer::Visions),
+    ("steampowered", Retailer::Steam),
+];
+
+/// Second-level labels that come before a country code, as in `amazon.co.uk`.
+const SECOND_LEVEL_SUFFIXES: &[&str] = &["co", "com", "net", "org"];
+
+/// Short names the subreddit uses in titles, compared whole.
+const RETAILER_ABBREVIATIONS: &[(&str, Retailer)] = &[
+    ("cc", Retailer::CanadaComputers),
+    ("bb", Retailer::BestBuy),
+    ("bby", Retailer::BestBuy),
+    ("mx", Retailer::MemoryExpress),
+    ("me", Retailer::MemoryExpress),
+];
+
+impl Retailer {
+    pub fn from_domain(domain: &str) -> Retailer {
+        let domain = domain.trim_start_matches("www.").to_lowercase();
+        let name = registrable_name(&domain);
+        RETAILER_HOSTS
+            .iter()
+            .find(|(host, _)| *host == name)
+            .map(|(_, retailer)| retailer.clone())
+            .unwrap_or(Retailer::Other(domain))
+    }
+
+    /// A retailer as named in a post title, e.g. `Bestbuy.ca`, `CC` or `Newegg Canada`.
+    pub fn from_name(name: &str) -> Option<Retailer> {
+        let squashed = squash(name);
+        RETAILER_ABBREVIATIONS
+            .iter()
+            .find(|(abbreviation, _)| *abbreviation == squashed)
+            .map(|(_, retailer)| retailer.clone())
+            .or_else(|| Self::find(&squashed))
+    }
+
+    fn find(squashed: &str) -> Option<Retailer> {
+        RETAILER_NAMES
+            .iter()
+            .find(|(needle, _)| squashed.contains(needle))
+            .map(|(_, retailer)| retailer.clone())
+    }
+
+    pub fn display_name(&self) -> &str {
+        match self {
+            Retailer::Amazon => "Amazon",
+            Retailer::Newegg => "Newegg",
+            Retailer::BestBuy => "Best Buy",
+            Retailer::CanadaComputers => "Canada Computers",
+            Retailer::MemoryExpress => "Memory Express",
+            Retailer::AliExpress => "AliExpress",
+            Retailer::Walmart => "Walmart",
+            Retailer::Costco => "Costco",
+            Retailer::Staples => "Staples",
+            Retailer::TheSource => "The Source",
+            Retailer::Visions => "Visions",
+            Retailer::Steam => "Steam",
+            Retailer::Other(domain) => domain,
+        }
+    }
+}
+
+/// The label a site registered, e.g. `amazon` for `smile.amazon.co.uk`.
+fn registrable_name(domain: &str) -> &str {
+    let labels: Vec<&str> = domain.split('.').collect();
+    let suffix_len = match labels.as_slice() {
+        [.., second, tld] if tld.len() == 2 && SECOND_LEVEL_SUFFIXES.contains(second) => 2,
+        _ => 1,
+    };
+    labels
+        .len()
+        .checked_sub(suffix_len + 1)
+        .map_or(domain, |i| labels[i])
+}
+
+fn squash(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// An outbound link with redirects unwrapped and tracking removed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NormalizedLink {
+    /// The cleaned URL, or the canonical product page when the product id is known.
+    pub url: String,
+    pub retailer: Retailer,
+    /// The retailer's own id, e.g. an Amazon ASIN or a Best Buy SKU.
+    pub product_id: Option<String>,
+}
+
+/// The post's outbound link, normalized. `None` for self posts and links back into Reddit.
+pub fn normalize_link(link: &RedditLink) -> Option<NormalizedLink> {
+    if link.is_self {
+        return None;
+    }
+    let raw = link.url_overridden_by_dest.as_deref().unwrap_or(&link.url);
+    let normalized = normalize_url(raw).ok()?;
+    let into_reddit = matches!(
+        &normalized.retailer,
+        Retailer::Other(domain) if domain.ends_with("reddit.com") || domain == "redd.it"
+    );
+    (!into_reddit).then_some(normalized)
+}
+
+/// Unwrap known redirectors, drop tracking parameters and canonicalize product pages.
+pub fn normalize_url(raw: &str) -> eyre::Result<NormalizedLink> {
+    // Titles and urls fetched without `raw_json=1` come HTML-escaped
+    let mut url = Url::parse(&raw.trim().replace("&amp;", "&"))?;
+    for _ in 0..MAX_REDIRECT_DEPTH {
+        match unwrap_redirect(&url) {
+            Some(target) => url = target,
+            None => break,
+        }
+    }
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    let retailer = Retailer::from_domain(&host);
+    strip_tracking(&mut url, &retailer);
+    url.set_fragment(None);
+    let product_id = product_id(&retailer, &url);
+    let canonical = product_id
+        .as_deref()
+        .and_then(|id| canonical_url(&retailer, &host, id));
+    Ok(NormalizedLink {
+        url: canonical.unwrap_or_else(|| url.to_string()),
+        retailer,
+        product_id,
+    })
+}
+
+/// The destination of an affiliate or tracking redirect, if `url` is one.
+fn unwrap_redirect(url: &Url) -> Option<Url> {
+    let host = url.host_str()?.to_lowercase();
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .and_then(|(_, v)| Url::parse(&v).ok())
+    };
+    match host.as_str() {
+        "click.linksynergy.com" | "linksynergy.walmart.com" => param("murl"),
+        "go.redirectingat.com" | "go.skimresources.com" => param("url"),
+        "redirect.viglink.com" | "l.facebook.com" | "lm.facebook.com" => param("u"),
+        "out.reddit.com" => param("url"),
+        "www.google.com" | "www.google.ca" if url.path() == "/url" => {
+            param("q").or_else(|| param("url"))
+        }
+        "href.li" => Url::parse(url.query()?).ok(),
+        // Commission Junction puts the target in the path or a `url` parameter
+        "www.anrdoezrs.net" | "www.jdoqocy.com" | "www.tkqlhce.com" | "www.dpbolvw.net"
+        | "www.kqzyfj.com" => param("url").or_else(|| {
+            let path = url.path();
+            let start = path.find("http")?;
+            Url::parse(&urlencoding_decode(&path[start..])).ok()
+        }),
+        // Impact links, e.g. `bestbuyca.o93x.net/c/.../?u=...`
+        _ if ["o93x.net", "sjv.io", "pxf.io", "evyy.net", "7eer.net"]
+            .iter()
+            .any(|d| host.ends_with(d)) =>
+        {
+            param("u")
+        }
+        _ => None,
+    }
+}
+
+fn urlencoding_decode(text: &str) -> String {
+    url::form_urlencoded::parse(format!("x={text}").as_bytes())
+        .next()
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_else(|| text.to_string())
+}
+
+fn strip_tracking(url: &mut Url, retailer: &Retailer) {
+    let (retailer_params, retailer_prefixes) = RETAILER_TRACKING
+        .iter()
+        .find(|(r, _, _)| r == retailer)
+        .map_or((&[][..], &[][..]), |(_, params, prefixes)| {
+            (*params, *prefixes)
+        });
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| {
+            let lower = k.to_lowercase();
+            !TRACKING_PARAMS
+                .iter()
+                .chain(retailer_params)
+                .any(|p| p.eq_ignore_ascii_case(k))
+                && !TRACKING_PREFIXES
+                    .iter()
+                    .chain(retailer_prefixes)
+                    .any(|p| lower.starts_with(p))
+        })
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+}
+
+fn product_id(retailer: &Retailer, url: &Url) -> Option<String> {
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let after = |marker: &str| {
+        segments
+            .iter()
+            .position(|s| s.eq_ignore_ascii_case(marker))
+            .and_then(|i| segments.get(i + 1))
+            .map(|s| s.to_string())
+    };
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.into_owned())
+    };
+    let id = match retailer {
+        // `/dp/{asin}`, `/gp/product/{asin}` or `/gp/aw/d/{asin}`
+        Retailer::Amazon => after("dp")
+            .or_else(|| after("product"))
+            .or_else(|| after("d"))
+            .filter(|id| id.len() == 10 && id.chars().all(|c| c.is_ascii_alphanumeric()))
+            .map(|id| id.to_uppercase()),
+        // `/{slug}/p/{item}` or `Product.aspx?Item={item}`
+        Retailer::Newegg => after("p").or_else(|| param("Item")),
+        // `/en-ca/product/{slug}/{sku}`
+        Retailer::BestBuy => segments
+            .last()
+            .filter(|s| s.chars().all(|c| c.is_ascii_digit()))
+            .map(|s| s.to_string()),
+        // `/en/{category}/{item_id}/{slug}.html` or `product_info.php?item_id={item_id}`
+        Retailer::CanadaComputers => param("item_id").or_else(|| {
+            segments
+                .iter()
+                .rev()
+                .skip(1)
+                .find(|s| s.chars().all(|c| c.is_ascii_digit()))
+                .map(|s| s.to_string())
+        }),
+        // `/Products/{MX id}`
+        Retailer::MemoryExpress => after("Products"),
+        // `/item/{id}.html`
+        Retailer::AliExpress => after("item").map(|s| s.trim_end_matches(".html").to_string()),
+        _ => None,
+    };
+    id.filter(|id| !id.is_empty())
+}
+
+fn canonical_url(retailer: &Retailer, host: &str, id: &str) -> Option<String> {
+    Some(match retailer {
+        // Keep the country site the post linked to
+        Retailer::Amazon => format!("https://{host}/dp/{id}"),
+        Retailer::Newegg => format!("https://{host}/p/{id}"),
+        Retailer::BestBuy => format!("https://www.bestbuy.ca/en-ca/product/{id}"),
+        Retailer::CanadaComputers => {
+            format!("https://www.canadacomputers.com/product_info.php?item_id={id}")
+        }
+        Retailer::MemoryExpress => format!("https://www.memoryexpress.com/Products/{id}"),
+        Retailer::AliExpress => format!("https://www.aliexpress.com/item/{id}.html"),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(raw: &str, retailer: Retailer, id: Option<&str>, url: &str) {
+        let link = normalize_url(raw).unwrap();
+        assert_eq!(link.retailer, retailer, "{raw}");
+        assert_eq!(link.product_id.as_deref(), id, "{raw}");
+        assert_eq!(link.url, url, "{raw}");
+    }
+
+    #[test]
+    fn canonicalizes_product_pages() {
+        check(
+            "https://www.amazon.ca/Corsair-Vengeance/dp/b0dkfmsmyk/ref=sr_1_3?crid=2X&tag=deals-20&th=1",
+            Retailer::Amazon,
+            Some("B0DKFMSMYK"),
+            "https://www.amazon.ca/dp/B0DKFMSMYK",
+        );
+        check(
+            "https://www.amazon.ca/gp/product/B086MN2XYL",
+            Retailer::Amazon,
+            Some("B086MN2XYL"),
+            "https://www.amazon.ca/dp/B086MN2XYL",
+        );
+        check(
+            "https://www.newegg.ca/asrock-rx6600-cld-8g/p/N82E16814930066?Item=N82E16814930066",
+            Retailer::Newegg,
+            Some("N82E16814930066"),
+            "https://www.newegg.ca/p/N82E16814930066",
+        );
+        check(
+            "https://www.bestbuy.ca/en-ca/product/intel-arc-b580-12gb-gddr6-video-card/18923211?icid=home",
+            Retailer::BestBuy,
+            Some("18923211"),
+            "https://www.bestbuy.ca/en-ca/product/18923211",
+        );
+        check(
+            "https://www.canadacomputers.com/en/amd-desktop-processors/264908/amd-ryzen-7-9800x3d.html",
+            Retailer::CanadaComputers,
+            Some("264908"),
+            "https://www.canadacomputers.com/product_info.php?item_id=264908",
+        );
+        check(
+            "https://www.canadacomputers.com/product_info.php?cPath=4_64&item_id=264908",
+            Retailer::CanadaComputers,
+            Some("264908"),
+            "https://www.canadacomputers.com/product_info.php?item_id=264908",
+        );
+        check(
+            "https://www.memoryexpress.com/Products/MX00131759?utm_source=reddit",
+            Retailer::MemoryExpress,
+            Some("MX00131759"),
+            "https://www.memoryexpress.com/Products/MX00131759",
+        );
+        check(
+            "https://www.aliexpress.com/item/1005008072964215.html?spm=a2g0o.cart.0.0&amp;mp=1#nav-review",
+            Retailer::AliExpress,
+            Some("1005008072964215"),
+            "https://www.aliexpress.com/item/1005008072964215.html",
+        );
+    }
+
+    #[test]
+    fn unwraps_redirects_and_strips_tracking() {
+        check(
+            "https://bestbuyca.o93x.net/c/123/10221/10221?u=https%3A%2F%2Fwww.bestbuy.ca%2Fen-ca%2Fproduct%2Fx%2F17932912",
+            Retailer::BestBuy,
+            Some("17932912"),
+            "https://www.bestbuy.ca/en-ca/product/17932912",
+        );
+        check(
+            "https://click.linksynergy.com/deeplink?id=abc&mid=1&murl=https%3A%2F%2Fwww.newegg.ca%2Fp%2FN82E16814930051%3Futm_medium%3Daff",
+            Retailer::Newegg,
+            Some("N82E16814930051"),
+            "https://www.newegg.ca/p/N82E16814930051",
+        );
+        check(
+            "https://www.anrdoezrs.net/links/123/type/dlg/https://www.staples.ca/products/2982349-en?utm_source=x",
+            Retailer::Staples,
+            None,
+            "https://www.staples.ca/products/2982349-en",
+        );
+        check(
+            "https://store.steampowered.com/sale/steamdeckrefurbished/?utm_campaign=deck&snr=1",
+            Retailer::Steam,
+            None,
+            "https://store.steampowered.com/sale/steamdeckrefurbished/?snr=1",
+        );
+        check(
+            "https://www.example.com/thing",
+            Retailer::Other("example.com".to_string()),
+            None,
+            "https://www.example.com/thing",
+        );
+    }
+
+    #[test]
+    fn retailer_tracking_only_on_retailer_sites() {
+        check(
+            "https://shop.example.com/item?ref=blue&tag=ssd&sr=2&fbclid=x&utm_source=reddit",
+            Retailer::Other("shop.example.com".to_string()),
+            None,
+            "https://shop.example.com/item?ref=blue&tag=ssd&sr=2",
+        );
+        check(
+            "https://www.amazon.co.uk/s?k=ssd&ref=nb_sb&pd_rd_w=abc",
+            Retailer::Amazon,
+            None,
+            "https://www.amazon.co.uk/s?k=ssd",
+        );
+    }
+
+    #[test]
+    fn retailers_by_registrable_host() {
+        assert_eq!(Retailer::from_domain("smile.amazon.ca"), Retailer::Amazon);
+        assert_eq!(Retailer::from_domain("amzn.to"), Retailer::Amazon);
+        assert_eq!(Retailer::from_domain("www.amazon.co.uk"), Retailer::Amazon);
+        assert_eq!(Retailer::from_domain("www.amazon.com.au"), Retailer::Amazon);
+        assert_eq!(
+            Retailer::from_domain("store.steampowered.com"),
+            Retailer::Steam
+        );
+        assert_eq!(
+            Retailer::from_domain("steamy-deals.com"),
+            Retailer::Other("steamy-deals.com".to_string())
+        );
+        assert_eq!(
+            Retailer::from_domain("visionsoftware.ca"),
+            Retailer::Other("visionsoftware.ca".to_string())
+        );
+        assert_eq!(
+            Retailer::from_domain("amazon.example.com"),
+            Retailer::Other("amazon.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn retailer_names_from_titles() {
+        assert_eq!(Retailer::from_name("CC"), Some(Retailer::CanadaComputers));
+        assert_eq!(Retailer::from_name("Bestbuy.ca"), Some(Retailer::BestBuy));
+        assert_eq!(Retailer::from_name("Best Buy"), Some(Retailer::BestBuy));
+        assert_eq!(
+            Retailer::from_name("Amazon warehouse"),
+            Some(Retailer::Amazon)
+        );
+        assert_eq!(Retailer::from_name("Newegg Canada"), Some(Retailer::Newegg));
+        assert_eq!(Retailer::from_name("Some Shop"), None);
+    }
+}
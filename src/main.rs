@@ -1,47 +1,681 @@
-use client::fetch_link_comments;
-use client::fetch_subreddit_posts_paginated;
-use client::SubredditSlug;
-use reqwest::header::HeaderMap;
-use reqwest::header::HeaderValue;
-use reqwest::header::USER_AGENT;
-
-pub mod client;
-pub mod lazy;
-pub mod models;
-pub mod rate_limit;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use eyre::bail;
+use futures::StreamExt;
+use reddit::alerts::AlertEngine;
+use reddit::alerts::AlertRules;
+use reddit::client::base_url;
+use reddit::client::build_client_with_user_agent;
+use reddit::client::cache_dir;
+use reddit::client::fetch_link_comments;
+use reddit::client::fetch_sorted_subreddit_page;
+use reddit::client::fetch_user_page;
+use reddit::client::parse_post_id;
+use reddit::client::refresh_link_comments;
+use reddit::client::refresh_sorted_subreddit_page;
+use reddit::client::refresh_user_page;
+use reddit::client::set_cache_dir;
+use reddit::client::ListingSort;
+use reddit::client::SubredditSlug;
+use reddit::client::DEFAULT_CACHE_DIR;
+use reddit::client::DEFAULT_USER_AGENT;
+use reddit::comment_tree::CommentTree;
+use reddit::crawl::CrawlJob;
+use reddit::crawl::DEFAULT_CRAWL_CONCURRENCY;
+use reddit::export::cached_comment_trees;
+use reddit::export::cached_links;
+use reddit::export::flatten_comments;
+use reddit::export::write_csv;
+use reddit::export::write_ndjson;
+use reddit::export::write_parquet;
+use reddit::export::Record;
+use reddit::models::now_utc;
+use reddit::models::RedditComment;
+use reddit::models::RedditLink;
+use reddit::models::RedditThing;
+use reddit::notify::Template;
+use reddit::notify::DEFAULT_TEMPLATE;
+use reddit::rate_limit::set_offline;
+use reddit::search::HitKind;
+use reddit::search::SearchHit;
+use reddit::search::SearchIndex;
+use reddit::storage::Archive;
+use reddit::watch::WatchEvent;
+use reddit::watch::Watcher;
+use serde::Serialize;
+use tracing::warn;
+
+/// Fetch, cache, search and export Reddit posts and comments.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// The user agent to send. Reddit asks for `<platform>:<app id>:<version> (by /u/<username>)`.
+    #[arg(long, global = true, default_value = DEFAULT_USER_AGENT)]
+    user_agent: String,
+    /// Where fetched responses are cached.
+    #[arg(long, global = true, default_value = DEFAULT_CACHE_DIR)]
+    cache_dir: PathBuf,
+    /// Only read the cache; fail instead of fetching anything not cached.
+    #[arg(long, global = true)]
+    offline: bool,
+    #[arg(long, short, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    /// A single JSON array.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    Csv,
+    Parquet,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Sort {
+    Hot,
+    New,
+    Top,
+    Rising,
+    Controversial,
+}
+
+impl From<Sort> for ListingSort {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Hot => ListingSort::Hot,
+            Sort::New => ListingSort::New,
+            Sort::Top => ListingSort::Top,
+            Sort::Rising => ListingSort::Rising,
+            Sort::Controversial => ListingSort::Controversial,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportKind {
+    Posts,
+    Comments,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List a subreddit's posts.
+    Posts {
+        subreddit: String,
+        #[arg(long, value_enum, default_value_t = Sort::Hot)]
+        sort: Sort,
+        #[arg(long, default_value_t = 1)]
+        pages: usize,
+        /// Fetch the pages again even if they're cached.
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show a post's comments.
+    Comments {
+        /// The post's id, fullname, permalink or URL.
+        post: String,
+        /// Fetch the comments again even if they're cached.
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// List a user's posts, or their comments.
+    User {
+        name: String,
+        #[arg(long)]
+        comments: bool,
+        #[arg(long, default_value_t = 1)]
+        pages: usize,
+        /// Fetch the pages again even if they're cached.
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Search the local index of posts and comments, kept in `<cache dir>/search`.
+    Search {
+        /// A query in tantivy's syntax, e.g. `"3080 ti" subreddit:bapcsalescanada`.
+        query: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Index every cached post and comment first.
+        #[arg(long)]
+        index_cache: bool,
+        /// Index what's new in this SQLite archive first.
+        #[arg(long)]
+        archive: Option<PathBuf>,
+    },
+    /// Print a subreddit's new posts and comments as they appear.
+    Watch {
+        subreddit: String,
+        /// Print alerts for posts matching these TOML or YAML rules instead.
+        #[arg(long)]
+        rules: Option<PathBuf>,
+        /// Fastest polling interval, in seconds.
+        #[arg(long, default_value_t = 10)]
+        min_interval: u64,
+        /// Slowest polling interval, in seconds.
+        #[arg(long, default_value_t = 120)]
+        max_interval: u64,
+    },
+    /// Export cached posts or comments.
+    Export {
+        #[arg(value_enum)]
+        kind: ExportKind,
+        /// Only posts from this subreddit's cached pages.
+        #[arg(long)]
+        subreddit: Option<String>,
+        /// Comma-separated columns for ndjson and csv; every column if not given.
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<String>,
+        /// Write here instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Fetch a subreddit's posts and all their comments, resuming where the last run stopped.
+    Crawl {
+        subreddit: String,
+        #[arg(long, default_value_t = 5)]
+        pages: usize,
+        /// How many posts' comments to fetch at once.
+        #[arg(long, default_value_t = DEFAULT_CRAWL_CONCURRENCY)]
+        concurrency: usize,
+    },
+    /// Inspect or clear the response cache.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Print where the cache is.
+    Path,
+    /// Count the files and bytes in each part of the cache.
+    Stats,
+    /// Delete every part of the cache, or just one such as `posts` or `subreddit`.
+    ///
+    /// Only the parts this tool writes are removed, so pointing `--cache-dir`
+    /// at a directory holding anything else leaves the rest alone.
+    Clear { section: Option<String> },
+}
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     color_eyre::install()?;
+    // Warnings only; tantivy logs every commit at info
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(tracing::Level::WARN)
+        .init();
 
-    // 1) Fetch 5 pages of posts
-    let sub = SubredditSlug::new("bapcsalescanada");
-    let all_links = fetch_subreddit_posts_paginated(sub, 5).await?;
-    println!("Fetched {} links total", all_links.len());
-
-    // 2) Build a client to reuse for all comment fetches
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        USER_AGENT,
-        HeaderValue::from_str("windows:ca.teamdman.myredditapp:v0.0.1 (by /u/TeamDman)")?,
-    );
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()?;
-
-    // 3) For each post, fetch comments. In a real-world scenario, you might want
-    //    concurrency, but that can clash with the 1req/sec limit. We'll do them
-    //    sequentially for simplicity:
-    for link in &all_links {
-        println!("Fetching comments for post {} - '{}'", link.id, link.title);
-        let comments = fetch_link_comments(
-            &client,
-            &link.id,
-            format!("https://www.reddit.com/{}", link.permalink).as_ref(),
-        )
-        .await?;
-        println!("  -> Found {} top-level comments", comments.len());
+    let cli = Cli::parse();
+    set_cache_dir(&cli.cache_dir);
+    set_offline(cli.offline);
+    let client = build_client_with_user_agent(&cli.user_agent)?;
+    let format = cli.format;
+
+    match cli.command {
+        Command::Posts {
+            subreddit,
+            sort,
+            pages,
+            refresh,
+        } => {
+            let subreddit = SubredditSlug::new(subreddit);
+            let mut links = Vec::new();
+            let sort = ListingSort::from(sort);
+            let mut after: Option<String> = None;
+            for page_idx in 0..pages {
+                let after_ref = after.as_deref();
+                let listing = if refresh {
+                    refresh_sorted_subreddit_page(&client, &subreddit, sort, page_idx, after_ref)
+                        .await?
+                } else {
+                    fetch_sorted_subreddit_page(&client, &subreddit, sort, page_idx, after_ref)
+                        .await?
+                };
+                links.extend(
+                    listing
+                        .children
+                        .into_iter()
+                        .filter_map(|thing| match thing {
+                            RedditThing::Link(link) => Some(link),
+                            _ => None,
+                        }),
+                );
+                after = listing.after;
+                if after.is_none() {
+                    break;
+                }
+            }
+            print_records(
+                format,
+                &links.iter().collect::<Vec<_>>(),
+                "posts",
+                link_line,
+            )?;
+        }
+        Command::Comments { post, refresh } => {
+            let Some(id) = parse_post_id(&post) else {
+                bail!("{post:?} isn't a post id, fullname, permalink or URL");
+            };
+            let link_url = format!("{}/comments/{id}", base_url());
+            let comments = if refresh {
+                refresh_link_comments(&client, &id, &link_url).await?
+            } else {
+                fetch_link_comments(&client, &id, &link_url).await?
+            };
+            let tree = CommentTree::from(comments);
+            let comments: Vec<&RedditComment> = tree.iter().collect();
+            print_records(format, &comments, "comments", |comment| {
+                format!(
+                    "{}{}",
+                    "  ".repeat(comment.depth.max(0) as usize),
+                    comment_line(comment)
+                )
+            })?;
+        }
+        Command::User {
+            name,
+            comments,
+            pages,
+            refresh,
+        } => {
+            let mut things = Vec::new();
+            let mut after: Option<String> = None;
+            for page_idx in 0..pages {
+                let after_ref = after.as_deref();
+                let listing = if refresh {
+                    refresh_user_page(&client, &name, comments, page_idx, after_ref).await?
+                } else {
+                    fetch_user_page(&client, &name, comments, page_idx, after_ref).await?
+                };
+                things.extend(listing.children);
+                after = listing.after;
+                if after.is_none() {
+                    break;
+                }
+            }
+            if comments {
+                let comments: Vec<&RedditComment> = things
+                    .iter()
+                    .filter_map(|thing| match thing {
+                        RedditThing::Comment(comment) => Some(comment),
+                        _ => None,
+                    })
+                    .collect();
+                print_records(format, &comments, "comments", comment_line)?;
+            } else {
+                let links: Vec<&RedditLink> = things
+                    .iter()
+                    .filter_map(|thing| match thing {
+                        RedditThing::Link(link) => Some(link),
+                        _ => None,
+                    })
+                    .collect();
+                print_records(format, &links, "posts", link_line)?;
+            }
+        }
+        Command::Search {
+            query,
+            limit,
+            index_cache,
+            archive,
+        } => {
+            let mut index = SearchIndex::open(cache_dir().join("search"))?;
+            if index_cache {
+                for link in cached_links(None).await? {
+                    index.add_link(&link)?;
+                }
+                for (_, tree) in cached_comment_trees().await? {
+                    index.add_comment_tree(&tree)?;
+                }
+                index.commit()?;
+            }
+            if let Some(path) = archive {
+                let added = index.update_from_archive(&Archive::open(path)?)?;
+                eprintln!("Indexed {added} new posts and comments from the archive");
+            }
+            print_search_hits(format, &index.search(&query, limit)?)?;
+        }
+        Command::Watch {
+            subreddit,
+            rules,
+            min_interval,
+            max_interval,
+        } => {
+            if !matches!(
+                format,
+                OutputFormat::Text | OutputFormat::Json | OutputFormat::Ndjson
+            ) {
+                bail!("watch prints text, json or ndjson");
+            }
+            let watcher = Watcher::new(client.clone(), SubredditSlug::new(&subreddit))
+                .with_interval(
+                    Duration::from_secs(min_interval),
+                    Duration::from_secs(max_interval),
+                );
+            let mut events = std::pin::pin!(watcher.into_stream(100));
+            let mut engine = match rules {
+                Some(path) => Some(AlertEngine::new(AlertRules::load(path).await?)),
+                None => None,
+            };
+            let template = Template::new(DEFAULT_TEMPLATE);
+            // Rules with a minimum score after some minutes need posts looked at again
+            let mut recheck = tokio::time::interval(Duration::from_secs(60));
+            eprintln!("Watching r/{subreddit}");
+            loop {
+                let alerts = tokio::select! {
+                    event = events.next() => {
+                        let Some(event) = event else { break };
+                        match &mut engine {
+                            Some(engine) => engine.offer_event(event, now_utc()),
+                            None => {
+                                print_event(format, &event)?;
+                                continue;
+                            }
+                        }
+                    }
+                    _ = recheck.tick() => match &mut engine {
+                        Some(engine) => match engine.recheck_pending(&client, now_utc()).await {
+                            Ok(alerts) => alerts,
+                            Err(e) => {
+                                // Pending posts stay queued for the next tick
+                                warn!("Rechecking pending posts failed: {e:#}");
+                                continue;
+                            }
+                        },
+                        None => continue,
+                    },
+                };
+                for alert in alerts {
+                    match format {
+                        OutputFormat::Text => println!("{}", template.render(&alert)),
+                        _ => println!("{}", serde_json::to_string(&alert)?),
+                    }
+                }
+            }
+        }
+        Command::Export {
+            kind,
+            subreddit,
+            columns,
+            output,
+        } => {
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            let out: Box<dyn Write + Send> = match &output {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout()),
+            };
+            if output.is_none() {
+                check_stdout_format(format)?;
+            }
+            let count = match kind {
+                ExportKind::Posts => {
+                    let subreddit = subreddit.map(SubredditSlug::new);
+                    let links = cached_links(subreddit.as_ref()).await?;
+                    let links: Vec<&RedditLink> = links.iter().collect();
+                    write_records(out, format, &links, &columns, "posts", link_line)?
+                }
+                ExportKind::Comments => {
+                    let trees: Vec<CommentTree> = cached_comment_trees()
+                        .await?
+                        .into_iter()
+                        .map(|(_, tree)| tree)
+                        .collect();
+                    let comments = flatten_comments(&trees);
+                    write_records(out, format, &comments, &columns, "comments", comment_line)?
+                }
+            };
+            eprintln!("Exported {count} records");
+        }
+        Command::Crawl {
+            subreddit,
+            pages,
+            concurrency,
+        } => {
+            let checkpoint = cache_dir().join("crawl").join(format!("{subreddit}.json"));
+            let mut job =
+                CrawlJob::resume_or_new(client, SubredditSlug::new(&subreddit), pages, checkpoint)
+                    .await?
+                    .with_concurrency(concurrency);
+            eprintln!("Starting crawl: {}", job.progress());
+            // Retry anything that failed last time, then run to completion,
+            // checkpointing after every request
+            job.retry_failures().await?;
+            let progress = job.run(|progress| eprintln!("  -> {progress}")).await?;
+            eprintln!("Finished crawl: {progress}");
+            for failure in job.state.failures.values() {
+                eprintln!(
+                    "  Post {} failed {} time(s): {}",
+                    failure.post.id, failure.attempts, failure.error
+                );
+            }
+        }
+        Command::Cache { action } => cache_command(format, action)?,
     }
+    Ok(())
+}
 
+fn link_line(link: &RedditLink) -> String {
+    format!(
+        "{:>6} {:>5}  {}  https://www.reddit.com{}",
+        link.score, link.num_comments, link.title, link.permalink
+    )
+}
+
+fn comment_line(comment: &RedditComment) -> String {
+    let body = comment
+        .body
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("u/{} ({}): {}", comment.author, comment.score, body)
+}
+
+/// Parquet is binary, so don't dump it on a terminal.
+fn check_stdout_format(format: OutputFormat) -> eyre::Result<()> {
+    if format == OutputFormat::Parquet && std::io::stdout().is_terminal() {
+        bail!("Parquet is binary; redirect stdout to a file or use `export --output`");
+    }
     Ok(())
 }
+
+fn print_records<R: Record + Serialize>(
+    format: OutputFormat,
+    records: &[&R],
+    schema_name: &str,
+    text: impl Fn(&R) -> String,
+) -> eyre::Result<()> {
+    check_stdout_format(format)?;
+    write_records(std::io::stdout(), format, records, &[], schema_name, text)?;
+    Ok(())
+}
+
+/// Write `records` in `format`, using `text` to render each one as plain text.
+fn write_records<R: Record + Serialize>(
+    mut out: impl Write + Send,
+    format: OutputFormat,
+    records: &[&R],
+    columns: &[&str],
+    schema_name: &str,
+    text: impl Fn(&R) -> String,
+) -> eyre::Result<usize> {
+    match format {
+        OutputFormat::Text => {
+            for record in records {
+                writeln!(out, "{}", text(record))?;
+            }
+            out.flush()?;
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, records)?;
+            writeln!(out)?;
+            out.flush()?;
+        }
+        OutputFormat::Ndjson => {
+            write_ndjson(out, records.iter().copied(), columns)?;
+        }
+        OutputFormat::Csv => {
+            write_csv(out, records.iter().copied(), columns)?;
+        }
+        OutputFormat::Parquet => write_parquet(out, records, schema_name)?,
+    }
+    Ok(records.len())
+}
+
+fn print_search_hits(format: OutputFormat, hits: &[SearchHit]) -> eyre::Result<()> {
+    let mut out = std::io::stdout().lock();
+    match format {
+        OutputFormat::Text => {
+            for hit in hits {
+                let post = hit.link_id.trim_start_matches("t3_");
+                let (what, url) = match hit.kind {
+                    HitKind::Link => (
+                        hit.title.clone(),
+                        format!("https://www.reddit.com/comments/{post}"),
+                    ),
+                    HitKind::Comment => (
+                        format!("comment by u/{}", hit.author),
+                        format!(
+                            "https://www.reddit.com/comments/{post}/_/{}",
+                            hit.name.trim_start_matches("t1_")
+                        ),
+                    ),
+                };
+                writeln!(
+                    out,
+                    "{:>7.2}  r/{}  {what}  {url}",
+                    hit.score, hit.subreddit
+                )?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, hits)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            for hit in hits {
+                serde_json::to_writer(&mut out, hit)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(out);
+            for hit in hits {
+                csv.serialize(hit)?;
+            }
+            csv.flush()?;
+        }
+        OutputFormat::Parquet => bail!("search prints text, json, ndjson or csv"),
+    }
+    Ok(())
+}
+
+fn print_event(format: OutputFormat, event: &WatchEvent) -> eyre::Result<()> {
+    match (format, event) {
+        (OutputFormat::Text, WatchEvent::NewPost(link)) => println!("{}", link_line(link)),
+        (OutputFormat::Text, WatchEvent::NewComment(comment)) => {
+            println!("{}", comment_line(comment))
+        }
+        (_, WatchEvent::NewPost(link)) => println!("{}", serde_json::to_string(link)?),
+        (_, WatchEvent::NewComment(comment)) => println!("{}", serde_json::to_string(comment)?),
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct CacheSection {
+    name: String,
+    files: u64,
+    bytes: u64,
+}
+
+/// The directories under the cache dir that this tool writes.
+const CACHE_SECTIONS: &[&str] = &["crawl", "posts", "search", "subreddit", "users"];
+
+fn cache_command(format: OutputFormat, action: CacheAction) -> eyre::Result<()> {
+    let root = cache_dir();
+    match action {
+        CacheAction::Path => println!("{}", root.display()),
+        CacheAction::Stats => {
+            let mut sections = Vec::new();
+            if root.exists() {
+                for entry in std::fs::read_dir(&root)? {
+                    let entry = entry?;
+                    let (files, bytes) = dir_size(&entry.path())?;
+                    sections.push(CacheSection {
+                        name: entry.file_name().to_string_lossy().into_owned(),
+                        files,
+                        bytes,
+                    });
+                }
+            }
+            sections.sort_by(|a, b| a.name.cmp(&b.name));
+            match format {
+                OutputFormat::Json | OutputFormat::Ndjson => {
+                    println!("{}", serde_json::to_string_pretty(&sections)?)
+                }
+                _ => {
+                    for section in &sections {
+                        println!(
+                            "{:<12} {:>7} files {:>12} bytes",
+                            section.name, section.files, section.bytes
+                        );
+                    }
+                }
+            }
+        }
+        CacheAction::Clear { section } => {
+            let sections = match &section {
+                Some(section) if CACHE_SECTIONS.contains(&section.as_str()) => {
+                    vec![section.as_str()]
+                }
+                Some(section) => bail!(
+                    "{section:?} isn't a part of the cache, expected one of {}",
+                    CACHE_SECTIONS.join(", ")
+                ),
+                None => CACHE_SECTIONS.to_vec(),
+            };
+            let mut removed = false;
+            for section in sections {
+                let path = root.join(section);
+                if path.exists() {
+                    std::fs::remove_dir_all(&path)?;
+                    eprintln!("Removed {}", path.display());
+                    removed = true;
+                }
+            }
+            if !removed {
+                eprintln!("Nothing cached at {}", root.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Files and bytes under `path`, counting `path` itself if it's a file.
+fn dir_size(path: &Path) -> eyre::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok((1, metadata.len()));
+    }
+    let (mut files, mut bytes) = (0, 0);
+    for entry in std::fs::read_dir(path)? {
+        let (f, b) = dir_size(&entry?.path())?;
+        files += f;
+        bytes += b;
+    }
+    Ok((files, bytes))
+}
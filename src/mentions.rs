@@ -0,0 +1,365 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::comment_tree::CommentTree;
+use crate::deals::find_coupon_codes;
+use crate::deals::Currency;
+use crate::deals::Deal;
+use crate::links::normalize_link;
+use crate::links::normalize_url;
+use crate::links::NormalizedLink;
+use crate::links::Retailer;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+
+/// Hosts people link for pictures or discussion rather than to buy something.
+const NON_OFFER_HOSTS: &[&str] = &[
+    "imgur.com",
+    "redd.it",
+    "reddit.com",
+    "youtu.be",
+    "youtube.com",
+];
+
+/// Prices in a comment below this fraction of the post's are taken to be for
+/// something else, like shipping or an accessory, rather than a better offer.
+const MIN_PRICE_FRACTION: f64 = 0.5;
+
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>()\[\]"*`]+"#).unwrap());
+static HREF: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"href="([^"]+)""#).unwrap());
+
+/// `$214.37`, `$161.99USD`, `US $139.92`, `$207.33 cad` or `276.37 USD`.
+static AMOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:\b(US|CA|C)\s?)?\$\s*(\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?)(\+)?(?:\s*(?i:(usd|cad|cdn|us))\b)?|\b(\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+\.\d{2})\s*(?i:(usd|cad|cdn))\b",
+    )
+    .unwrap()
+});
+/// Words before an amount that make it a spending minimum, e.g. "orders $150".
+static MINIMUM_BEFORE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(orders?|over|spend|min(imum)?|off)\s*$").unwrap());
+static OFF_AFTER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^\s*off\b").unwrap());
+
+/// Emphasized tokens like `**25LD12**`, which count as codes when the comment talks about codes.
+static EMPHASIZED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\*\*([A-Z0-9-]{4,20})\*\*|`([A-Z0-9-]{4,20})`").unwrap());
+static MENTIONS_CODES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(code|coupon|promo)").unwrap());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Amount {
+    pub value: f64,
+    pub currency: Currency,
+    pub kind: AmountKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountKind {
+    Price,
+    /// "$20 off".
+    Discount,
+    /// What a coupon needs spent first, e.g. "orders $150+".
+    Minimum,
+}
+
+/// What a comment mentions that could be an offer of its own.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommentMentions {
+    /// The comment's fullname.
+    pub comment: String,
+    /// The post's fullname.
+    pub link_id: String,
+    pub author: String,
+    pub score: i64,
+    /// Links in the body and its HTML, normalized and without repeats.
+    pub urls: Vec<NormalizedLink>,
+    pub coupon_codes: Vec<String>,
+    pub amounts: Vec<Amount>,
+}
+
+impl CommentMentions {
+    pub fn extract(comment: &RedditComment) -> CommentMentions {
+        let mut urls: Vec<NormalizedLink> = Vec::new();
+        for raw in find_urls(&comment.body, &comment.body_html) {
+            if let Ok(normalized) = normalize_url(&raw) {
+                if !urls.iter().any(|u| u.url == normalized.url) {
+                    urls.push(normalized);
+                }
+            }
+        }
+        CommentMentions {
+            comment: comment.name.clone(),
+            link_id: comment.link_id.clone(),
+            author: comment.author.clone(),
+            score: comment.score,
+            urls,
+            coupon_codes: find_codes(&comment.body),
+            amounts: find_amounts(&comment.body),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty() && self.coupon_codes.is_empty() && self.amounts.is_empty()
+    }
+
+    /// The lowest plain price in `currency` that could be for the same thing as
+    /// `price`, leaving out discounts, minimums and amounts like "$5 shipping".
+    pub fn comparable_price(&self, currency: Currency, price: f64) -> Option<f64> {
+        self.amounts
+            .iter()
+            .filter(|a| a.kind == AmountKind::Price && a.currency == currency)
+            .filter(|a| a.value >= price * MIN_PRICE_FRACTION)
+            .map(|a| a.value)
+            .min_by(f64::total_cmp)
+    }
+}
+
+/// Mentions from every comment in the thread that has any.
+pub fn thread_mentions(tree: &CommentTree) -> Vec<CommentMentions> {
+    tree.iter()
+        .map(CommentMentions::extract)
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// A better price, a code or another store found in a post's comments.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlternativeOffer {
+    pub mentions: CommentMentions,
+    /// Codes the post's title doesn't already give.
+    pub new_coupon_codes: Vec<String>,
+    /// Links to somewhere to buy other than the post's own link.
+    pub other_urls: Vec<NormalizedLink>,
+    /// How much less than the post's price the comment's lowest comparable
+    /// price is, in the post's currency.
+    pub savings: Option<f64>,
+}
+
+/// Comments offering something the post doesn't: a lower price, a new code or
+/// another store. Biggest savings first, then the highest scoring.
+pub fn alternative_offers(link: &RedditLink, tree: &CommentTree) -> Vec<AlternativeOffer> {
+    let deal = Deal::from_link(link);
+    let post_url = normalize_link(link).map(|n| n.url);
+    let mut offers: Vec<AlternativeOffer> = thread_mentions(tree)
+        .into_iter()
+        .filter_map(|mentions| {
+            let new_coupon_codes: Vec<String> = mentions
+                .coupon_codes
+                .iter()
+                .filter(|code| deal.as_ref().is_none_or(|d| !d.coupon_codes.contains(code)))
+                .cloned()
+                .collect();
+            let other_urls: Vec<NormalizedLink> = mentions
+                .urls
+                .iter()
+                .filter(|u| is_offer_url(u) && post_url.as_ref() != Some(&u.url))
+                .cloned()
+                .collect();
+            let savings = deal.as_ref().and_then(|d| {
+                let price = d.price?;
+                let lowest = mentions.comparable_price(d.currency, price)?;
+                (lowest < price).then_some(price - lowest)
+            });
+            if new_coupon_codes.is_empty() && other_urls.is_empty() && savings.is_none() {
+                return None;
+            }
+            Some(AlternativeOffer {
+                mentions,
+                new_coupon_codes,
+                other_urls,
+                savings,
+            })
+        })
+        .collect();
+    offers.sort_by(|a, b| {
+        let savings = |o: &AlternativeOffer| o.savings.unwrap_or(f64::MIN);
+        savings(b)
+            .total_cmp(&savings(a))
+            .then(b.mentions.score.cmp(&a.mentions.score))
+    });
+    offers
+}
+
+fn is_offer_url(link: &NormalizedLink) -> bool {
+    match &link.retailer {
+        Retailer::Other(host) => !NON_OFFER_HOSTS
+            .iter()
+            .any(|h| host == h || host.ends_with(&format!(".{h}"))),
+        _ => true,
+    }
+}
+
+/// Links written out in the body and the targets of links in its HTML.
+fn find_urls(body: &str, body_html: &str) -> Vec<String> {
+    let html = body_html
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&");
+    URL.find_iter(body)
+        .map(|m| {
+            m.as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', '\''])
+        })
+        .chain(
+            HREF.captures_iter(&html)
+                .map(|c| c.get(1).unwrap().as_str()),
+        )
+        .map(str::to_string)
+        .collect()
+}
+
+fn find_codes(body: &str) -> Vec<String> {
+    let mut emphasized = Vec::new();
+    if MENTIONS_CODES.is_match(body) {
+        emphasized = EMPHASIZED
+            .captures_iter(body)
+            .filter_map(|c| c.get(1).or(c.get(2)))
+            .map(|m| m.as_str().to_string())
+            // Bold words like **NOTE** aren't codes
+            .filter(|t| {
+                t.chars().any(|c| c.is_ascii_digit()) && t.chars().any(|c| c.is_ascii_uppercase())
+            })
+            .collect();
+    }
+    let mut codes: Vec<String> = Vec::new();
+    for code in find_coupon_codes(body).into_iter().chain(emphasized) {
+        if !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+    codes
+}
+
+fn find_amounts(body: &str) -> Vec<Amount> {
+    AMOUNT
+        .captures_iter(body)
+        .filter_map(|c| {
+            let whole = c.get(0).unwrap();
+            let number = c.get(2).or(c.get(5))?.as_str();
+            let value: f64 = number.replace(',', "").parse().ok()?;
+            let usd = c.get(1).is_some_and(|p| p.as_str() == "US")
+                || c.get(4)
+                    .or(c.get(6))
+                    .is_some_and(|s| s.as_str().to_lowercase().starts_with("us"));
+            let kind = if OFF_AFTER.is_match(&body[whole.end()..]) {
+                AmountKind::Discount
+            } else if c.get(3).is_some() || MINIMUM_BEFORE.is_match(&body[..whole.start()]) {
+                AmountKind::Minimum
+            } else {
+                AmountKind::Price
+            };
+            Some(Amount {
+                value,
+                currency: if usd { Currency::Usd } else { Currency::Cad },
+                kind,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::IgnoredAny;
+
+    use crate::client::parse_link_comments;
+    use crate::models::RedditResponse;
+    use crate::models::RedditThing;
+
+    use super::*;
+
+    #[test]
+    fn finds_amounts_and_codes() {
+        let amounts = find_amounts(
+            "It was $161.99USD, now US $139.92 with $20 off orders $150+. Paid 207.33 cad",
+        );
+        let summary: Vec<(f64, Currency, AmountKind)> = amounts
+            .iter()
+            .map(|a| (a.value, a.currency, a.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (161.99, Currency::Usd, AmountKind::Price),
+                (139.92, Currency::Usd, AmountKind::Price),
+                (20.0, Currency::Cad, AmountKind::Discount),
+                (150.0, Currency::Cad, AmountKind::Minimum),
+                (207.33, Currency::Cad, AmountKind::Price),
+            ]
+        );
+
+        assert_eq!(find_codes("if you use coupon code 25LD20"), vec!["25LD20"]);
+        assert_eq!(find_codes("There's a coupon: **25LD12**"), vec!["25LD12"]);
+        assert!(find_codes("**NOTE** a coupon for $18 off").is_empty());
+        assert!(find_codes("Got the **7800X3D** yesterday").is_empty());
+
+        let urls = find_urls(
+            "Cheaper at https://www.amazon.ca/dp/B0BBHD5D8Y?tag=foo. Also [here](https://imgur.com/a/x)",
+            r#"&lt;a href="https://imgur.com/a/x"&gt;here&lt;/a&gt;"#,
+        );
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.amazon.ca/dp/B0BBHD5D8Y?tag=foo",
+                "https://imgur.com/a/x",
+                "https://imgur.com/a/x",
+            ]
+        );
+    }
+
+    #[test]
+    fn small_amounts_are_not_savings() {
+        let mentions = CommentMentions {
+            comment: "t1_a".to_string(),
+            link_id: "t3_a".to_string(),
+            author: "someone".to_string(),
+            score: 1,
+            urls: Vec::new(),
+            coupon_codes: Vec::new(),
+            amounts: find_amounts("Got it for $199.99 but paid $5 shipping"),
+        };
+        assert_eq!(
+            mentions.comparable_price(Currency::Cad, 232.37),
+            Some(199.99)
+        );
+        assert_eq!(mentions.comparable_price(Currency::Cad, 500.0), None);
+    }
+
+    #[test]
+    fn example_thread_offers() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let (RedditResponse::Listing(listing), IgnoredAny) = serde_json::from_str(&text)?;
+        let Some(RedditThing::Link(link)) = listing.children.into_iter().next() else {
+            eyre::bail!("no link in the post payload");
+        };
+        let tree = CommentTree::from(parse_link_comments(&text)?);
+
+        let mentions = thread_mentions(&tree);
+        assert!(mentions.iter().all(|m| m.link_id == link.name));
+        let codes: Vec<&str> = mentions
+            .iter()
+            .flat_map(|m| m.coupon_codes.iter().map(String::as_str))
+            .collect();
+        assert!(codes.contains(&"25LD12"));
+        assert!(codes.contains(&"25LD20"));
+
+        // The post is $232.37; the pictures link isn't somewhere to buy
+        let offers = alternative_offers(&link, &tree);
+        assert!(!offers.is_empty());
+        assert!(offers.iter().all(|o| o.other_urls.is_empty()));
+        let coupon = offers
+            .iter()
+            .find(|o| o.mentions.coupon_codes.contains(&"25LD12".to_string()))
+            .unwrap();
+        assert_eq!(coupon.savings.map(|s| (s * 100.0).round()), Some(1800.0));
+        assert!(offers
+            .windows(2)
+            .all(|w| w[0].savings.unwrap_or(f64::MIN) >= w[1].savings.unwrap_or(f64::MIN)));
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
+use serde_json::value::RawValue;
 
+use crate::lazy::Lazy;
+use crate::lazy::LazyError;
 use crate::lazy::LazyResponse;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -22,6 +28,7 @@ pub struct RedditListing {
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(tag = "kind", content = "data")]
+#[allow(clippy::large_enum_variant)]
 pub enum RedditThing {
     #[serde(rename = "t1")]
     Comment(RedditComment),
@@ -35,6 +42,21 @@ pub enum RedditThing {
     Subreddit,
     #[serde(rename = "t6")]
     Award,
+    #[serde(rename = "more")]
+    More(RedditMore),
+}
+
+/// A stub standing in for comments left out of a listing, as in large threads.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RedditMore {
+    /// How many comments the stub stands for.
+    pub count: i64,
+    pub name: String,
+    pub id: String,
+    pub parent_id: String,
+    pub depth: i64,
+    /// IDs of the left out comments, to pass to `/api/morechildren`.
+    pub children: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -43,7 +65,7 @@ pub struct RedditComment {
     pub approved_at_utc: Option<serde_json::Value>,
     pub author_is_blocked: bool,
     pub comment_type: Option<serde_json::Value>,
-    pub awarders: Vec<Option<serde_json::Value>>,
+    pub awarders: Vec<String>,
     pub mod_reason_by: Option<serde_json::Value>,
     pub banned_by: Option<serde_json::Value>,
     pub author_flair_type: Option<String>,
@@ -69,12 +91,12 @@ pub struct RedditComment {
     pub author_fullname: Option<String>,
     pub approved_by: Option<serde_json::Value>,
     pub mod_note: Option<serde_json::Value>,
-    pub all_awardings: Vec<Option<serde_json::Value>>,
+    pub all_awardings: Vec<Awarding>,
     pub collapsed: bool,
     pub body: String,
     #[serde(default, deserialize_with = "false_as_none")]
     pub edited: Option<f64>,
-    pub top_awarded_type: Option<serde_json::Value>,
+    pub top_awarded_type: Option<String>,
     pub author_flair_css_class: Option<serde_json::Value>,
     pub name: String,
     pub is_submitter: bool,
@@ -89,7 +111,7 @@ pub struct RedditComment {
     pub stickied: bool,
     pub author_premium: Option<bool>,
     pub can_gild: bool,
-    pub gildings: serde_json::Value,
+    pub gildings: Gildings,
     pub unrepliable_reason: Option<serde_json::Value>,
     pub author_flair_text_color: Option<String>,
     pub score_hidden: bool,
@@ -103,6 +125,8 @@ pub struct RedditComment {
     pub link_id: String,
     pub subreddit_name_prefixed: String,
     pub controversiality: i64,
+    /// Missing from comments in a user's listing, which aren't part of a tree.
+    #[serde(default)]
     pub depth: i64,
     pub author_flair_background_color: Option<String>,
     pub collapsed_because_crowd_control: Option<serde_json::Value>,
@@ -115,22 +139,39 @@ pub struct RedditComment {
     pub replies: Option<LazyResponse>,
 }
 
-fn empty_string_or_map_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+impl RedditComment {
+    pub fn award_summary(&self) -> AwardSummary {
+        AwardSummary::new(&self.all_awardings)
+    }
+
+    /// Direct replies to this comment, parsing the `replies` listing on first use.
+    ///
+    /// Fails if the `replies` listing doesn't parse, so callers can tell a
+    /// comment with no replies apart from one whose replies couldn't be read.
+    pub fn reply_comments(&self) -> Result<impl Iterator<Item = &RedditComment>, LazyError> {
+        let children = match self.replies.as_ref().map(|r| r.get()).transpose()? {
+            Some(RedditResponse::Listing(listing)) => listing.children.as_slice(),
+            None => &[],
+        };
+        Ok(children.iter().filter_map(|thing| match thing {
+            RedditThing::Comment(comment) => Some(comment),
+            _ => None,
+        }))
+    }
+}
+
+/// Reddit sends `""` instead of a listing when a comment has no replies.
+///
+/// Reads the field as a [`RawValue`] so nested reply listings are not walked
+/// until someone asks for them.
+fn empty_string_or_map_as_none<'de, D, T>(deserializer: D) -> Result<Option<Lazy<T>>, D::Error>
 where
     D: Deserializer<'de>,
-    T: DeserializeOwned,
 {
-    let value: serde_json::Value = Deserialize::deserialize(deserializer)?;
-    match value {
-        serde_json::Value::String(s) if s.is_empty() => Ok(None), // Empty string -> None
-        serde_json::Value::Object(_) => {
-            // Try to deserialize the map object into T
-            let map = serde_json::from_value(value)
-                .map(Some)
-                .map_err(serde::de::Error::custom)?;
-            Ok(map)
-        }
-        _ => Ok(None), // Anything else -> None
+    let raw = Box::<RawValue>::deserialize(deserializer)?;
+    match raw.get().trim_start().as_bytes().first() {
+        Some(b'{') => Ok(Some(Lazy::from_raw(raw))), // Map -> deferred parse
+        _ => Ok(None),                               // Empty string or anything else -> None
     }
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -157,7 +198,7 @@ pub struct RedditLink {
     pub link_flair_css_class: Option<String>,
     pub downs: i64,
     pub thumbnail_height: Option<i64>,
-    pub top_awarded_type: Option<serde_json::Value>,
+    pub top_awarded_type: Option<String>,
     pub hide_score: bool,
     pub name: String,
     pub quarantine: bool,
@@ -167,16 +208,16 @@ pub struct RedditLink {
     pub subreddit_type: SubredditType,
     pub ups: i64,
     pub total_awards_received: i64,
-    pub media_embed: serde_json::Value,
+    pub media_embed: MediaEmbed,
     pub thumbnail_width: Option<i64>,
     pub author_flair_template_id: Option<String>,
     pub is_original_content: bool,
     pub user_reports: Vec<Option<serde_json::Value>>,
-    pub secure_media: Option<serde_json::Value>,
+    pub secure_media: Option<Media>,
     pub is_reddit_media_domain: bool,
     pub is_meta: bool,
     pub category: Option<serde_json::Value>,
-    pub secure_media_embed: serde_json::Value,
+    pub secure_media_embed: MediaEmbed,
     pub link_flair_text: Option<String>,
     pub can_mod_post: bool,
     pub score: i64,
@@ -188,14 +229,14 @@ pub struct RedditLink {
     pub edited: Option<f64>,
     pub author_flair_css_class: Option<serde_json::Value>,
     pub author_flair_richtext: Vec<FlairRichtext>,
-    pub gildings: serde_json::Value,
+    pub gildings: Gildings,
     pub content_categories: Option<serde_json::Value>,
     pub is_self: bool,
     pub mod_note: Option<serde_json::Value>,
     pub created: f64,
     pub link_flair_type: AuthorFlairType,
     pub wls: i64,
-    pub removed_by_category: Option<serde_json::Value>,
+    pub removed_by_category: Option<String>,
     pub banned_by: Option<serde_json::Value>,
     pub author_flair_type: AuthorFlairType,
     pub domain: String,
@@ -210,8 +251,8 @@ pub struct RedditLink {
     pub is_crosspostable: bool,
     pub pinned: bool,
     pub over_18: bool,
-    pub all_awardings: Vec<Option<serde_json::Value>>,
-    pub awarders: Vec<Option<serde_json::Value>>,
+    pub all_awardings: Vec<Awarding>,
+    pub awarders: Vec<String>,
     pub media_only: bool,
     pub link_flair_template_id: Option<String>,
     pub can_gild: bool,
@@ -245,7 +286,19 @@ pub struct RedditLink {
     pub subreddit_subscribers: i64,
     pub created_utc: f64,
     pub num_crossposts: i64,
-    pub media: Option<serde_json::Value>,
+    pub media: Option<Media>,
+    #[serde(default)]
+    pub media_metadata: Option<HashMap<String, MediaMetadata>>,
+    #[serde(default)]
+    pub is_gallery: Option<bool>,
+    #[serde(default)]
+    pub gallery_data: Option<GalleryData>,
+    #[serde(default)]
+    pub poll_data: Option<PollData>,
+    #[serde(default)]
+    pub crosspost_parent: Option<String>,
+    #[serde(default)]
+    pub crosspost_parent_list: Option<Vec<RedditLink>>,
     pub is_video: bool,
     pub author_cakeday: Option<bool>,
     pub post_hint: Option<String>,
@@ -253,6 +306,57 @@ pub struct RedditLink {
     pub url_overridden_by_dest: Option<String>,
 }
 
+impl RedditLink {
+    pub fn award_summary(&self) -> AwardSummary {
+        AwardSummary::new(&self.all_awardings)
+    }
+
+    /// The hosted video for this post, preferring `secure_media` over `media`.
+    pub fn reddit_video(&self) -> Option<&RedditVideo> {
+        self.secure_media
+            .as_ref()
+            .and_then(|m| m.reddit_video.as_ref())
+            .or_else(|| self.media.as_ref().and_then(|m| m.reddit_video.as_ref()))
+    }
+
+    /// The oEmbed description for this post, preferring `secure_media` over `media`.
+    pub fn oembed(&self) -> Option<&OEmbed> {
+        self.secure_media
+            .as_ref()
+            .and_then(|m| m.oembed.as_ref())
+            .or_else(|| self.media.as_ref().and_then(|m| m.oembed.as_ref()))
+    }
+
+    /// Gallery items in display order, each paired with its entry in `media_metadata`.
+    pub fn gallery_images(&self) -> Vec<GalleryImage<'_>> {
+        let Some(gallery) = &self.gallery_data else {
+            return vec![];
+        };
+        gallery
+            .items
+            .iter()
+            .map(|item| GalleryImage {
+                item,
+                metadata: self
+                    .media_metadata
+                    .as_ref()
+                    .and_then(|m| m.get(&item.media_id)),
+            })
+            .collect()
+    }
+}
+
+/// The current time as unix seconds, comparable with `created_utc`.
+pub fn now_utc() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Reddit sends `false` in place of a missing object. Serializing the field
+/// back out writes `null` for `None`, as the archive does for links it is
+/// handed already parsed, so `null` reads back as `None` too.
 fn false_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
 where
     D: Deserializer<'de>,
@@ -260,7 +364,7 @@ where
 {
     let value: serde_json::Value = Deserialize::deserialize(deserializer)?;
     match value {
-        serde_json::Value::Bool(false) => Ok(None), // false -> None
+        serde_json::Value::Bool(false) | serde_json::Value::Null => Ok(None), // false or null -> None
         _ => {
             // Try to deserialize the value into T
             let map = serde_json::from_value(value)
@@ -289,14 +393,80 @@ pub struct Preview {
     pub enabled: bool,
 }
 
+impl Preview {
+    /// The first preview image sized for display at `max_width`.
+    pub fn thumbnail(&self, max_width: i64) -> Option<&ImageSource> {
+        self.images
+            .first()
+            .map(|image| image.best_under_width(max_width))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct Image {
     pub source: ImageSource,
     pub resolutions: Vec<ImageSource>,
-    pub variants: serde_json::Value,
+    pub variants: ImageVariants,
     pub id: String,
 }
 
+impl Image {
+    /// The widest rendition no wider than `max_width`, falling back to the narrowest.
+    pub fn best_under_width(&self, max_width: i64) -> &ImageSource {
+        best_under_width(&self.source, &self.resolutions, max_width)
+    }
+
+    /// Like [`Image::best_under_width`], but uses the blurred `nsfw` or `obfuscated`
+    /// rendition when one is present.
+    pub fn safe_best_under_width(&self, max_width: i64) -> &ImageSource {
+        match self
+            .variants
+            .nsfw
+            .as_ref()
+            .or(self.variants.obfuscated.as_ref())
+        {
+            Some(variant) => variant.best_under_width(max_width),
+            None => self.best_under_width(max_width),
+        }
+    }
+}
+
+/// Alternate renditions of a preview image. Reddit sends `{}` when there are none.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct ImageVariants {
+    pub gif: Option<ImageVariant>,
+    pub mp4: Option<ImageVariant>,
+    pub obfuscated: Option<ImageVariant>,
+    pub nsfw: Option<ImageVariant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ImageVariant {
+    pub source: ImageSource,
+    pub resolutions: Vec<ImageSource>,
+}
+
+impl ImageVariant {
+    /// The widest rendition no wider than `max_width`, falling back to the narrowest.
+    pub fn best_under_width(&self, max_width: i64) -> &ImageSource {
+        best_under_width(&self.source, &self.resolutions, max_width)
+    }
+}
+
+fn best_under_width<'a>(
+    source: &'a ImageSource,
+    resolutions: &'a [ImageSource],
+    max_width: i64,
+) -> &'a ImageSource {
+    let candidates = || resolutions.iter().chain(std::iter::once(source));
+    candidates()
+        .filter(|image| image.width <= max_width)
+        .max_by_key(|image| image.width)
+        .or_else(|| candidates().min_by_key(|image| image.width))
+        .unwrap_or(source)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct ImageSource {
     pub url: String,
@@ -309,3 +479,515 @@ pub struct ImageSource {
 pub enum SubredditType {
     Public,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Media {
+    #[serde(default)]
+    pub reddit_video: Option<RedditVideo>,
+    /// The oEmbed provider domain, e.g. `youtube.com`.
+    #[serde(default, rename = "type")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub oembed: Option<OEmbed>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedditVideo {
+    pub bitrate_kbps: Option<i64>,
+    pub fallback_url: String,
+    #[serde(default)]
+    pub has_audio: Option<bool>,
+    pub height: i64,
+    pub width: i64,
+    pub scrubber_media_url: Option<String>,
+    pub dash_url: Option<String>,
+    /// Length of the video in seconds.
+    pub duration: f64,
+    pub hls_url: Option<String>,
+    pub is_gif: bool,
+    pub transcoding_status: Option<String>,
+}
+
+impl RedditVideo {
+    pub fn duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.duration.max(0.0))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct OEmbed {
+    pub provider_url: Option<String>,
+    pub provider_name: Option<String>,
+    pub version: Option<String>,
+    pub title: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub html: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub thumbnail_url: Option<String>,
+    pub thumbnail_width: Option<i64>,
+    pub thumbnail_height: Option<i64>,
+    pub author_name: Option<String>,
+    pub author_url: Option<String>,
+}
+
+/// Reddit sends `{}` when there is nothing to embed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct MediaEmbed {
+    pub content: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub scrolling: Option<bool>,
+    pub media_domain_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MediaMetadata {
+    pub status: String,
+    #[serde(default)]
+    pub e: Option<MediaMetadataKind>,
+    /// Mime type, e.g. `image/jpg`.
+    #[serde(default)]
+    pub m: Option<String>,
+    /// Preview renditions, smallest first.
+    #[serde(default)]
+    pub p: Vec<MediaMetadataImage>,
+    /// The full size source.
+    #[serde(default)]
+    pub s: Option<MediaMetadataImage>,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MediaMetadataKind {
+    Image,
+    AnimatedImage,
+    RedditVideo,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MediaMetadataImage {
+    #[serde(rename = "x")]
+    pub width: i64,
+    #[serde(rename = "y")]
+    pub height: i64,
+    #[serde(default, rename = "u")]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub gif: Option<String>,
+    #[serde(default)]
+    pub mp4: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GalleryData {
+    pub items: Vec<GalleryItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GalleryItem {
+    /// Key into `RedditLink::media_metadata`.
+    pub media_id: String,
+    pub id: i64,
+    #[serde(default)]
+    pub caption: Option<String>,
+    #[serde(default)]
+    pub outbound_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GalleryImage<'a> {
+    pub item: &'a GalleryItem,
+    pub metadata: Option<&'a MediaMetadata>,
+}
+
+impl GalleryImage<'_> {
+    pub fn caption(&self) -> Option<&str> {
+        self.item.caption.as_deref()
+    }
+
+    /// The full size image url, or the animated rendition for gifs.
+    pub fn source_url(&self) -> Option<&str> {
+        let source = self.metadata?.s.as_ref()?;
+        source
+            .url
+            .as_deref()
+            .or(source.gif.as_deref())
+            .or(source.mp4.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PollData {
+    pub options: Vec<PollOption>,
+    /// Null while the poll is open and results are hidden.
+    #[serde(default)]
+    pub total_vote_count: Option<i64>,
+    /// Milliseconds since the unix epoch.
+    pub voting_end_timestamp: i64,
+    #[serde(default)]
+    pub is_prediction: bool,
+    #[serde(default)]
+    pub user_selection: Option<String>,
+    #[serde(default)]
+    pub resolved_option_id: Option<String>,
+}
+
+impl PollData {
+    /// The option with the most votes, if vote counts are visible.
+    pub fn leading_option(&self) -> Option<&PollOption> {
+        self.options
+            .iter()
+            .filter(|o| o.vote_count.is_some())
+            .max_by_key(|o| o.vote_count)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PollOption {
+    pub id: String,
+    pub text: String,
+    #[serde(default)]
+    pub vote_count: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Awarding {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub coin_price: i64,
+    #[serde(default)]
+    pub coin_reward: i64,
+    #[serde(default)]
+    pub days_of_premium: Option<i64>,
+    /// How many times this award was given to the thing.
+    pub count: i64,
+    #[serde(default)]
+    pub award_type: Option<String>,
+    #[serde(default)]
+    pub award_sub_type: Option<String>,
+    pub icon_url: String,
+    #[serde(default)]
+    pub icon_width: Option<i64>,
+    #[serde(default)]
+    pub icon_height: Option<i64>,
+    #[serde(default)]
+    pub static_icon_url: Option<String>,
+    #[serde(default)]
+    pub resized_icons: Vec<ImageSource>,
+    #[serde(default)]
+    pub resized_static_icons: Vec<ImageSource>,
+    #[serde(default)]
+    pub subreddit_id: Option<String>,
+}
+
+/// Counts keyed by gilding id: `gid_1` (silver), `gid_2` (gold) and `gid_3` (platinum).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Gildings(pub BTreeMap<String, i64>);
+
+impl Gildings {
+    pub fn silver(&self) -> i64 {
+        self.get("gid_1")
+    }
+
+    pub fn gold(&self) -> i64 {
+        self.get("gid_2")
+    }
+
+    pub fn platinum(&self) -> i64 {
+        self.get("gid_3")
+    }
+
+    pub fn get(&self, gid: &str) -> i64 {
+        self.0.get(gid).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> i64 {
+        self.0.values().sum()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwardSummary {
+    /// Total number of awards given, counting repeats.
+    pub count: i64,
+    /// Total coins spent, i.e. `coin_price * count` over all awardings.
+    pub coins: i64,
+    /// Award count keyed by award name.
+    pub by_name: BTreeMap<String, i64>,
+}
+
+impl AwardSummary {
+    pub fn new(awardings: &[Awarding]) -> Self {
+        let mut summary = Self::default();
+        for awarding in awardings {
+            summary.count += awarding.count;
+            summary.coins += awarding.coin_price * awarding.count;
+            *summary.by_name.entry(awarding.name.clone()).or_default() += awarding.count;
+        }
+        summary
+    }
+
+    /// Combine summaries, e.g. across every comment in a thread.
+    pub fn merge(&mut self, other: &AwardSummary) {
+        self.count += other.count;
+        self.coins += other.coins;
+        for (name, count) in &other.by_name {
+            *self.by_name.entry(name.clone()).or_default() += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_payloads() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.json")?;
+        let jd = &mut serde_json::Deserializer::from_str(&text);
+        let _listing: RedditResponse = serde_path_to_error::deserialize(jd)?;
+
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let jd = &mut serde_json::Deserializer::from_str(&text);
+        let _post: (RedditResponse, RedditResponse) = serde_path_to_error::deserialize(jd)?;
+        Ok(())
+    }
+
+    #[test]
+    fn more_stubs() -> eyre::Result<()> {
+        // A trimmed thread with "load more" stubs at the top level and in a reply listing
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.more.json")?;
+        let jd = &mut serde_json::Deserializer::from_str(&text);
+        let (_link, comments): (RedditResponse, RedditResponse) =
+            serde_path_to_error::deserialize(jd)?;
+        let RedditResponse::Listing(listing) = comments;
+        let Some(RedditThing::More(more)) = listing.children.last() else {
+            panic!("expected a more stub last");
+        };
+        assert_eq!(more.count, 14);
+        assert_eq!(more.children.len(), 3);
+
+        let comments = crate::client::parse_link_comments(&text)?;
+        assert_eq!(comments.len(), 2);
+        let replies = comments[0]
+            .replies
+            .as_ref()
+            .expect("first comment has replies");
+        let RedditResponse::Listing(replies) = replies.get()?;
+        assert_eq!(replies.children.len(), 2);
+        assert!(matches!(replies.children[0], RedditThing::Comment(_)));
+        assert!(matches!(replies.children[1], RedditThing::More(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn reddit_video() -> eyre::Result<()> {
+        let x = r#"{
+  "reddit_video": {
+    "bitrate_kbps": 2400,
+    "fallback_url": "https://v.redd.it/abc/DASH_720.mp4?source=fallback",
+    "has_audio": true,
+    "height": 720,
+    "width": 1280,
+    "scrubber_media_url": "https://v.redd.it/abc/DASH_96.mp4",
+    "dash_url": "https://v.redd.it/abc/DASHPlaylist.mpd",
+    "duration": 31,
+    "hls_url": "https://v.redd.it/abc/HLSPlaylist.m3u8",
+    "is_gif": false,
+    "transcoding_status": "completed"
+  }
+}"#;
+        let media = serde_json::from_str::<Media>(x)?;
+        let video = media.reddit_video.expect("reddit_video");
+        assert_eq!(video.duration(), std::time::Duration::from_secs(31));
+        assert_eq!(
+            video.hls_url.as_deref(),
+            Some("https://v.redd.it/abc/HLSPlaylist.m3u8")
+        );
+        assert!(media.oembed.is_none());
+        Ok(())
+    }
+
+    /// The JSON of the first link in the example listing, with `fields` set.
+    fn example_link_json(fields: serde_json::Value) -> eyre::Result<serde_json::Value> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.json")?;
+        let mut listing: serde_json::Value = serde_json::from_str(&text)?;
+        let mut data = listing["data"]["children"][0]["data"].take();
+        for (key, value) in fields.as_object().into_iter().flatten() {
+            data[key] = value.clone();
+        }
+        Ok(data)
+    }
+
+    /// The first link in the example listing, with `fields` set in its JSON.
+    fn example_link_with(fields: serde_json::Value) -> eyre::Result<RedditLink> {
+        Ok(serde_json::from_value(example_link_json(fields)?)?)
+    }
+
+    #[test]
+    fn crosspost_parent_list() -> eyre::Result<()> {
+        let parent = example_link_with(serde_json::json!({}))?;
+        let crosspost = example_link_with(serde_json::json!({
+            "id": "xpost1",
+            "name": "t3_xpost1",
+            "crosspost_parent": parent.name,
+            "crosspost_parent_list": [example_link_json(serde_json::json!({}))?],
+        }))?;
+        assert_eq!(crosspost.crosspost_parent.as_ref(), Some(&parent.name));
+        let parents = crosspost
+            .crosspost_parent_list
+            .as_deref()
+            .unwrap_or_default();
+        assert_eq!(parents, [parent]);
+        Ok(())
+    }
+
+    #[test]
+    fn gallery_and_poll() -> eyre::Result<()> {
+        let gallery = r#"{
+  "items": [
+    {"media_id": "a1", "id": 1, "caption": "front"},
+    {"media_id": "b2", "id": 2}
+  ]
+}"#;
+        let metadata = r#"{
+  "a1": {
+    "status": "valid",
+    "e": "Image",
+    "m": "image/jpg",
+    "p": [{"y": 108, "x": 108, "u": "https://preview.redd.it/a1.jpg?width=108"}],
+    "s": {"y": 1024, "x": 1024, "u": "https://preview.redd.it/a1.jpg"},
+    "id": "a1"
+  },
+  "b2": {
+    "status": "valid",
+    "e": "AnimatedImage",
+    "m": "image/gif",
+    "s": {"y": 200, "x": 300, "gif": "https://i.redd.it/b2.gif"},
+    "id": "b2"
+  }
+}"#;
+        let link = example_link_with(serde_json::json!({
+            "gallery_data": serde_json::from_str::<serde_json::Value>(gallery)?,
+            "media_metadata": serde_json::from_str::<serde_json::Value>(metadata)?,
+        }))?;
+        let images = link.gallery_images();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].caption(), Some("front"));
+        assert_eq!(
+            images[0].source_url(),
+            Some("https://preview.redd.it/a1.jpg")
+        );
+        assert_eq!(images[1].source_url(), Some("https://i.redd.it/b2.gif"));
+
+        let poll = r#"{
+  "prediction_status": null,
+  "total_stake_amount": null,
+  "voting_end_timestamp": 1737936000000,
+  "options": [
+    {"text": "Yes", "vote_count": 12, "id": "1"},
+    {"text": "No", "vote_count": 30, "id": "2"}
+  ],
+  "vote_updates_remained": null,
+  "is_prediction": false,
+  "resolved_option_id": null,
+  "user_won_amount": null,
+  "user_selection": null,
+  "total_vote_count": 42,
+  "tournament_id": null
+}"#;
+        let poll: PollData = serde_json::from_str(poll)?;
+        assert_eq!(poll.leading_option().map(|o| o.text.as_str()), Some("No"));
+        Ok(())
+    }
+
+    #[test]
+    fn image_variants() -> eyre::Result<()> {
+        let x = r#"{
+  "source": {"url": "https://preview.redd.it/full.jpg", "width": 1200, "height": 630},
+  "resolutions": [
+    {"url": "https://preview.redd.it/108.jpg", "width": 108, "height": 56},
+    {"url": "https://preview.redd.it/320.jpg", "width": 320, "height": 168},
+    {"url": "https://preview.redd.it/640.jpg", "width": 640, "height": 336}
+  ],
+  "variants": {
+    "nsfw": {
+      "source": {"url": "https://preview.redd.it/full-blur.jpg", "width": 1200, "height": 630},
+      "resolutions": [
+        {"url": "https://preview.redd.it/108-blur.jpg", "width": 108, "height": 56}
+      ]
+    }
+  },
+  "id": "abc"
+}"#;
+        let image = serde_json::from_str::<Image>(x)?;
+        assert_eq!(image.best_under_width(400).width, 320);
+        assert_eq!(image.best_under_width(5000).width, 1200);
+        assert_eq!(image.best_under_width(50).width, 108);
+        assert_eq!(
+            image.safe_best_under_width(400).url,
+            "https://preview.redd.it/108-blur.jpg"
+        );
+        assert!(image.variants.gif.is_none());
+
+        let empty = serde_json::from_str::<ImageVariants>("{}")?;
+        assert_eq!(empty, ImageVariants::default());
+        Ok(())
+    }
+
+    #[test]
+    fn awardings() -> eyre::Result<()> {
+        let x = r#"[
+  {
+    "giver_coin_reward": null,
+    "subreddit_id": null,
+    "is_new": false,
+    "days_of_drip_extension": null,
+    "coin_price": 100,
+    "id": "award_5f123e3d-4f48-42f4-9c11-e98b566d5897",
+    "penny_donate": null,
+    "award_sub_type": "GLOBAL",
+    "coin_reward": 0,
+    "icon_url": "https://i.redd.it/award_images/t5_22cerq/5izbv4fn0md41_Wholesome.png",
+    "days_of_premium": null,
+    "resized_icons": [
+      {"url": "https://preview.redd.it/award_images/16.png", "width": 16, "height": 16}
+    ],
+    "icon_width": 2048,
+    "count": 2,
+    "name": "Wholesome",
+    "icon_height": 2048,
+    "award_type": "global",
+    "static_icon_url": "https://i.redd.it/award_images/t5_22cerq/5izbv4fn0md41_Wholesome.png"
+  },
+  {
+    "coin_price": 500,
+    "id": "gid_2",
+    "count": 1,
+    "name": "Gold",
+    "icon_url": "https://www.redditstatic.com/gold/awards/icon/gold_512.png"
+  }
+]"#;
+        let awardings = serde_json::from_str::<Vec<Awarding>>(x)?;
+        let summary = AwardSummary::new(&awardings);
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.coins, 700);
+        assert_eq!(summary.by_name.get("Wholesome"), Some(&2));
+
+        let gildings = serde_json::from_str::<Gildings>(r#"{"gid_1": 2, "gid_2": 1}"#)?;
+        assert_eq!(gildings.silver(), 2);
+        assert_eq!(gildings.gold(), 1);
+        assert_eq!(gildings.platinum(), 0);
+        assert_eq!(gildings.total(), 3);
+        Ok(())
+    }
+}
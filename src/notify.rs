@@ -0,0 +1,755 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::AsyncSmtpTransport;
+use lettre::AsyncTransport;
+use lettre::Message;
+use lettre::Tokio1Executor;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::alerts::Alert;
+use crate::persist::load_json_if_exists;
+use crate::persist::save_json;
+
+/// A one-line message with the post and where it links to.
+pub const DEFAULT_TEMPLATE: &str = "[{rule}] {title} {url} ({link})";
+
+/// A message with `{placeholder}`s filled in from an [`Alert`].
+///
+/// Placeholders are `rule`, `reasons`, `title`, `subreddit`, `author`,
+/// `score`, `url`, `permalink`, `link` (the full Reddit URL), and from the
+/// parsed deal `category`, `product`, `price` and `retailer`. Unknown
+/// placeholders are left as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(pub String);
+
+impl Default for Template {
+    fn default() -> Self {
+        Self(DEFAULT_TEMPLATE.to_string())
+    }
+}
+
+impl Template {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self(template.into())
+    }
+
+    pub fn render(&self, alert: &Alert) -> String {
+        let mut out = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+            match after.find('}').and_then(|close| {
+                let value = placeholder(alert, &after[..close])?;
+                Some((close, value))
+            }) {
+                Some((close, value)) => {
+                    out.push_str(&value);
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+fn placeholder(alert: &Alert, key: &str) -> Option<String> {
+    let deal = alert.deal.as_ref();
+    Some(match key {
+        "rule" => alert.rule.clone(),
+        "reasons" => alert.reasons.join(", "),
+        "title" => alert.title.clone(),
+        "subreddit" => alert.subreddit.clone(),
+        "author" => alert.author.clone(),
+        "score" => alert.score.to_string(),
+        "url" => alert.url.clone(),
+        "permalink" => alert.permalink.clone(),
+        "link" => format!("https://www.reddit.com{}", alert.permalink),
+        "category" => deal.and_then(|d| d.category.clone()).unwrap_or_default(),
+        "product" => deal.map(|d| d.product.clone()).unwrap_or_default(),
+        "price" => deal
+            .and_then(|d| d.price)
+            .map(|p| format!("${p:.2}"))
+            .unwrap_or_default(),
+        "retailer" => deal.and_then(|d| d.retailer.clone()).unwrap_or_default(),
+        _ => return None,
+    })
+}
+
+/// Somewhere alerts can be sent.
+pub trait Notifier: Send + Sync {
+    /// Identifies the destination, so [`Dispatcher`] can remember what it was sent.
+    fn name(&self) -> String;
+
+    fn notify<'a>(&'a self, alert: &'a Alert) -> BoxFuture<'a, eyre::Result<()>>;
+}
+
+/// POSTs `{"message": ..., "alert": {...}}` to any URL.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    template: Template,
+}
+
+impl WebhookNotifier {
+    pub fn new(client: reqwest::Client, url: impl Into<String>, template: Template) -> Self {
+        Self {
+            client,
+            url: url.into(),
+            template,
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> String {
+        format!("webhook:{}", redact_url(&self.url))
+    }
+
+    fn notify<'a>(&'a self, alert: &'a Alert) -> BoxFuture<'a, eyre::Result<()>> {
+        let payload = json!({
+            "message": self.template.render(alert),
+            "alert": alert,
+        });
+        Box::pin(post_json(&self.client, &self.url, payload))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatService {
+    /// Sends `{"content": ...}`.
+    Discord,
+    /// Sends `{"text": ...}`, which Slack and Mattermost accept.
+    Slack,
+}
+
+/// Posts the rendered message to a Discord or Slack incoming webhook.
+pub struct ChatWebhookNotifier {
+    client: reqwest::Client,
+    service: ChatService,
+    url: String,
+    template: Template,
+}
+
+impl ChatWebhookNotifier {
+    pub fn new(
+        client: reqwest::Client,
+        service: ChatService,
+        url: impl Into<String>,
+        template: Template,
+    ) -> Self {
+        Self {
+            client,
+            service,
+            url: url.into(),
+            template,
+        }
+    }
+}
+
+impl Notifier for ChatWebhookNotifier {
+    fn name(&self) -> String {
+        format!(
+            "{}:{}",
+            format!("{:?}", self.service).to_lowercase(),
+            redact_url(&self.url)
+        )
+    }
+
+    fn notify<'a>(&'a self, alert: &'a Alert) -> BoxFuture<'a, eyre::Result<()>> {
+        let message = self.template.render(alert);
+        let payload = match self.service {
+            // Discord rejects messages over 2000 characters
+            ChatService::Discord => {
+                json!({ "content": message.chars().take(2000).collect::<String>() })
+            }
+            ChatService::Slack => json!({ "text": message }),
+        };
+        Box::pin(post_json(&self.client, &self.url, payload))
+    }
+}
+
+/// A webhook URL's host and a digest of the rest. Discord and Slack put the
+/// webhook's secret token in the path, so names, logs and the sent file get this instead.
+fn redact_url(url: &str) -> String {
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            let host = u.host_str()?.to_string();
+            Some(match u.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host,
+            })
+        })
+        .unwrap_or_default();
+    // FNV-1a, which unlike `DefaultHasher` stays the same across Rust releases
+    let digest = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{host}/{:08x}", digest as u32)
+}
+
+/// A webhook answered with an error status.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: reqwest::StatusCode,
+    /// From a 429's `Retry-After` header, in seconds.
+    pub retry_after: Option<Duration>,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "webhook answered {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+async fn post_json(
+    client: &reqwest::Client,
+    url: &str,
+    payload: serde_json::Value,
+) -> eyre::Result<()> {
+    // reqwest puts the URL in its errors, and with it the webhook's token
+    let response = client
+        .post(url)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)?;
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok()?.trim().parse::<f64>().ok())
+            .map(|secs| Duration::from_secs_f64(secs.max(0.0)));
+        return Err(HttpStatusError {
+            status,
+            retry_after,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Emails alerts through an SMTP server.
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: Template,
+    body: Template,
+}
+
+impl SmtpNotifier {
+    /// Send through `host` over TLS, logging in if `credentials` are given.
+    pub fn relay(
+        host: &str,
+        credentials: Option<(String, String)>,
+        from: &str,
+        to: &[&str],
+    ) -> eyre::Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)?;
+        if let Some((user, password)) = credentials {
+            builder = builder.credentials(Credentials::new(user, password));
+        }
+        Self::with_transport(builder.build(), from, to)
+    }
+
+    /// Send in plain text to a local relay, such as one on `localhost:25`.
+    pub fn unencrypted(host: &str, port: u16, from: &str, to: &[&str]) -> eyre::Result<Self> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(port)
+            .build();
+        Self::with_transport(transport, from, to)
+    }
+
+    fn with_transport(
+        transport: AsyncSmtpTransport<Tokio1Executor>,
+        from: &str,
+        to: &[&str],
+    ) -> eyre::Result<Self> {
+        eyre::ensure!(
+            !to.is_empty(),
+            "An email notifier needs at least one recipient"
+        );
+        Ok(Self {
+            transport,
+            from: from.parse()?,
+            to: to.iter().map(|t| t.parse()).collect::<Result<_, _>>()?,
+            subject: Template::new("[{rule}] {title}"),
+            body: Template::new("{title}\n\n{url}\n{link}\n\nMatched because: {reasons}\n"),
+        })
+    }
+
+    pub fn with_templates(mut self, subject: Template, body: Template) -> Self {
+        self.subject = subject;
+        self.body = body;
+        self
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn name(&self) -> String {
+        let to: Vec<String> = self.to.iter().map(|m| m.email.to_string()).collect();
+        format!("smtp:{}", to.join(","))
+    }
+
+    fn notify<'a>(&'a self, alert: &'a Alert) -> BoxFuture<'a, eyre::Result<()>> {
+        Box::pin(async move {
+            let mut builder = Message::builder()
+                .from(self.from.clone())
+                .subject(self.subject.render(alert))
+                .header(ContentType::TEXT_PLAIN);
+            for to in &self.to {
+                builder = builder.to(to.clone());
+            }
+            let message = builder.body(self.body.render(alert))?;
+            self.transport.send(message).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Writes one rendered line per alert to stdout or appends it to a file.
+pub struct FileNotifier {
+    path: Option<PathBuf>,
+    template: Template,
+}
+
+impl FileNotifier {
+    pub fn stdout(template: Template) -> Self {
+        Self {
+            path: None,
+            template,
+        }
+    }
+
+    pub fn append(path: impl Into<PathBuf>, template: Template) -> Self {
+        Self {
+            path: Some(path.into()),
+            template,
+        }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn name(&self) -> String {
+        match &self.path {
+            Some(path) => format!("file:{}", path.display()),
+            None => "stdout".to_string(),
+        }
+    }
+
+    fn notify<'a>(&'a self, alert: &'a Alert) -> BoxFuture<'a, eyre::Result<()>> {
+        Box::pin(async move {
+            let line = format!("{}\n", self.template.render(alert));
+            match &self.path {
+                Some(path) => {
+                    if let Some(parent) = path.parent() {
+                        tokio::fs::create_dir_all(parent).await?;
+                    }
+                    let mut file = tokio::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .await?;
+                    file.write_all(line.as_bytes()).await?;
+                }
+                None => {
+                    let mut stdout = tokio::io::stdout();
+                    stdout.write_all(line.as_bytes()).await?;
+                    stdout.flush().await?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// What happened to one alert.
+#[derive(Debug, Default, PartialEq)]
+pub struct DispatchReport {
+    /// Notifiers that delivered the alert.
+    pub sent: Vec<String>,
+    /// Notifiers that already had this post, from this or an earlier rule.
+    pub skipped: Vec<String>,
+    /// Notifiers that failed every attempt, with the last error.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Sends alerts to every notifier, retrying failures and never sending a post
+/// to the same notifier twice.
+///
+/// What was sent where is saved to a JSON file so this holds across restarts.
+/// A notifier that fails every attempt will be tried again on the next
+/// alert for that post.
+pub struct Dispatcher {
+    notifiers: Vec<Box<dyn Notifier>>,
+    path: Option<PathBuf>,
+    /// `{notifier name} {post fullname}` pairs.
+    sent: BTreeSet<String>,
+    /// Tries per notifier, including the first.
+    pub attempts: u32,
+    /// Wait before the first retry, doubling after each.
+    pub backoff: Duration,
+}
+
+impl Dispatcher {
+    /// A dispatcher that remembers what it sent only while it lives.
+    pub fn new(notifiers: Vec<Box<dyn Notifier>>) -> Self {
+        Self {
+            notifiers,
+            path: None,
+            sent: BTreeSet::new(),
+            attempts: 3,
+            backoff: Duration::from_secs(2),
+        }
+    }
+
+    /// A dispatcher that remembers what it sent in `path`.
+    pub async fn open(
+        notifiers: Vec<Box<dyn Notifier>>,
+        path: impl Into<PathBuf>,
+    ) -> eyre::Result<Self> {
+        let path = path.into();
+        let sent = load_json_if_exists(&path).await?.unwrap_or_default();
+        Ok(Self {
+            path: Some(path),
+            sent,
+            ..Self::new(notifiers)
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub async fn dispatch(&mut self, alert: &Alert) -> eyre::Result<DispatchReport> {
+        let mut report = DispatchReport::default();
+        for notifier in &self.notifiers {
+            let name = notifier.name();
+            let key = format!("{name} {}", alert.link_name);
+            if self.sent.contains(&key) {
+                report.skipped.push(name);
+                continue;
+            }
+            let mut backoff = self.backoff;
+            let mut attempt = 1;
+            loop {
+                match notifier.notify(alert).await {
+                    Ok(()) => {
+                        self.sent.insert(key);
+                        report.sent.push(name);
+                        break;
+                    }
+                    Err(e) => {
+                        let status = e.downcast_ref::<HttpStatusError>();
+                        // Other client errors won't go away by asking again
+                        let permanent = status.is_some_and(|s| {
+                            s.status.is_client_error()
+                                && s.status != reqwest::StatusCode::TOO_MANY_REQUESTS
+                        });
+                        if permanent || attempt >= self.attempts {
+                            warn!("Notifying {name} of {} failed: {e:#}", alert.link_name);
+                            report.failed.push((name, format!("{e:#}")));
+                            break;
+                        }
+                        let wait = status.and_then(|s| s.retry_after).unwrap_or(backoff);
+                        tokio::time::sleep(wait).await;
+                        backoff *= 2;
+                        attempt += 1;
+                    }
+                }
+            }
+        }
+        if let Some(path) = &self.path {
+            if !report.sent.is_empty() {
+                save_json(path, &self.sent).await?;
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use tokio::io::AsyncBufReadExt;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::BufReader;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::deals::Deal;
+    use crate::test_support::temp_dir;
+
+    fn alert() -> Alert {
+        Alert {
+            rule: "cheap gpu".to_string(),
+            reasons: vec![
+                "category is GPU".to_string(),
+                "price $270.00 is at most $300.00".to_string(),
+            ],
+            link_name: "t3_1i9d6s2".to_string(),
+            subreddit: "bapcsalescanada".to_string(),
+            author: "someone".to_string(),
+            title: "[GPU] ASRock Radeon RX 6600 Challenger ($600 - $330 = $270) [Newegg Canada]"
+                .to_string(),
+            url: "https://www.newegg.ca/p/N82E16814930051".to_string(),
+            permalink: "/r/bapcsalescanada/comments/1i9d6s2/gpu_asrock/".to_string(),
+            score: 42,
+            created_utc: 1737800000.0,
+            deal: Deal::parse_title(
+                "[GPU] ASRock Radeon RX 6600 Challenger ($600 - $330 = $270) [Newegg Canada]",
+            ),
+        }
+    }
+
+    /// An HTTP server that answers with `statuses` in turn, then 200s, recording request bodies.
+    async fn stand_in_http(statuses: Vec<u16>) -> eyre::Result<(String, Arc<Mutex<Vec<String>>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/hook", listener.local_addr()?);
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let recorded = bodies.clone();
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let body = loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length || n == 0 {
+                            break body.to_string();
+                        }
+                    }
+                };
+                recorded.lock().unwrap().push(body);
+                let status = statuses.next().unwrap_or(200);
+                let retry_after = if status == 429 {
+                    "Retry-After: 0\r\n"
+                } else {
+                    ""
+                };
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\n{retry_after}Content-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        Ok((url, bodies))
+    }
+
+    /// Just enough of an SMTP server to accept mail, recording each message.
+    async fn stand_in_smtp() -> eyre::Result<(u16, Arc<Mutex<Vec<String>>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let recorded = messages.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let (read, mut write) = socket.into_split();
+                let mut lines = BufReader::new(read).lines();
+                write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_uppercase();
+                    let reply: &[u8] = if command.starts_with("EHLO") {
+                        b"250 localhost\r\n"
+                    } else if command.starts_with("DATA") {
+                        write.write_all(b"354 go ahead\r\n").await.unwrap();
+                        let mut message = String::new();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            message.push_str(&line);
+                            message.push('\n');
+                        }
+                        recorded.lock().unwrap().push(message);
+                        b"250 queued\r\n"
+                    } else if command.starts_with("QUIT") {
+                        write.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    } else {
+                        b"250 ok\r\n"
+                    };
+                    write.write_all(reply).await.unwrap();
+                }
+            }
+        });
+        Ok((port, messages))
+    }
+
+    #[test]
+    fn renders_templates() {
+        let template =
+            Template::new("{category} {product} for {price} at {retailer} {link} {nope}");
+        assert_eq!(
+            template.render(&alert()),
+            "GPU ASRock Radeon RX 6600 Challenger for $270.00 at Newegg Canada \
+             https://www.reddit.com/r/bapcsalescanada/comments/1i9d6s2/gpu_asrock/ {nope}"
+        );
+    }
+
+    #[tokio::test]
+    async fn webhooks_retry_and_dedupe() -> eyre::Result<()> {
+        let (hook_url, hook_bodies) = stand_in_http(vec![500, 503]).await?;
+        let (discord_url, discord_bodies) = stand_in_http(vec![]).await?;
+        let client = reqwest::Client::new();
+        let mut dispatcher = Dispatcher::new(vec![
+            Box::new(WebhookNotifier::new(
+                client.clone(),
+                &hook_url,
+                Template::default(),
+            )),
+            Box::new(ChatWebhookNotifier::new(
+                client,
+                ChatService::Discord,
+                &discord_url,
+                Template::new("{title}"),
+            )),
+        ]);
+        dispatcher.backoff = Duration::from_millis(1);
+
+        let report = dispatcher.dispatch(&alert()).await?;
+        assert_eq!(report.sent.len(), 2);
+        assert!(report.failed.is_empty());
+        // Webhook tokens stay out of names, which end up in logs and the sent file
+        assert!(report.sent.iter().all(|name| !name.contains("/hook")));
+        // Two failures, then success
+        assert_eq!(hook_bodies.lock().unwrap().len(), 3);
+        let payload: serde_json::Value = serde_json::from_str(&hook_bodies.lock().unwrap()[2])?;
+        assert_eq!(
+            serde_json::from_value::<Alert>(payload["alert"].clone())?,
+            alert()
+        );
+        let payload: serde_json::Value = serde_json::from_str(&discord_bodies.lock().unwrap()[0])?;
+        assert_eq!(payload["content"], alert().title.as_str());
+
+        // The same post from another rule isn't sent again
+        let mut other_rule = alert();
+        other_rule.rule = "any gpu".to_string();
+        let report = dispatcher.dispatch(&other_rule).await?;
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(hook_bodies.lock().unwrap().len(), 3);
+        assert_eq!(discord_bodies.lock().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_attempts() -> eyre::Result<()> {
+        let (url, bodies) = stand_in_http(vec![500, 500, 500, 500]).await?;
+        let mut dispatcher = Dispatcher::new(vec![Box::new(ChatWebhookNotifier::new(
+            reqwest::Client::new(),
+            ChatService::Slack,
+            &url,
+            Template::default(),
+        ))]);
+        dispatcher.attempts = 2;
+        dispatcher.backoff = Duration::from_millis(1);
+        let report = dispatcher.dispatch(&alert()).await?;
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(bodies.lock().unwrap().len(), 2);
+        assert!(
+            serde_json::from_str::<serde_json::Value>(&bodies.lock().unwrap()[0])?["text"]
+                .is_string()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() -> eyre::Result<()> {
+        let (url, bodies) = stand_in_http(vec![404, 429]).await?;
+        let mut dispatcher = Dispatcher::new(vec![Box::new(WebhookNotifier::new(
+            reqwest::Client::new(),
+            &url,
+            Template::default(),
+        ))]);
+        // Long enough to fail the test if Retry-After were ignored
+        dispatcher.backoff = Duration::from_secs(60);
+        let report = dispatcher.dispatch(&alert()).await?;
+        assert_eq!(report.failed.len(), 1);
+        assert!(!report.failed[0].1.contains("/hook"));
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+
+        // Rate limited, then sent after the server's Retry-After
+        let report = dispatcher.dispatch(&alert()).await?;
+        assert_eq!(report.sent.len(), 1);
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn emails_and_files() -> eyre::Result<()> {
+        let (port, messages) = stand_in_smtp().await?;
+        let dir = temp_dir("notify");
+        let path = dir.join("alerts.log");
+        let sent_path = dir.join("sent.json");
+
+        let notifiers = || -> eyre::Result<Vec<Box<dyn Notifier>>> {
+            Ok(vec![
+                Box::new(SmtpNotifier::unencrypted(
+                    "127.0.0.1",
+                    port,
+                    "Deals <deals@localhost>",
+                    &["me@localhost"],
+                )?),
+                Box::new(FileNotifier::append(
+                    &path,
+                    Template::new("{score} {title}"),
+                )),
+            ])
+        };
+        let mut dispatcher = Dispatcher::open(notifiers()?, &sent_path).await?;
+        let report = dispatcher.dispatch(&alert()).await?;
+        assert_eq!(
+            report.sent,
+            vec![
+                "smtp:me@localhost".to_string(),
+                format!("file:{}", path.display())
+            ]
+        );
+
+        let messages = messages.lock().unwrap().clone();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: [cheap gpu]"));
+        assert!(messages[0].contains("https://www.newegg.ca/p/N82E16814930051"));
+        let log = tokio::fs::read_to_string(&path).await?;
+        assert_eq!(log, format!("42 {}\n", alert().title));
+
+        // A restarted dispatcher remembers what it sent
+        let mut dispatcher = Dispatcher::open(notifiers()?, &sent_path).await?;
+        let report = dispatcher.dispatch(&alert()).await?;
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(tokio::fs::read_to_string(&path).await?.lines().count(), 1);
+        Ok(())
+    }
+}
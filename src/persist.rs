@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Read a JSON state file written by [`save_json`].
+pub async fn load_json<T: DeserializeOwned>(path: impl AsRef<Path>) -> eyre::Result<T> {
+    let text = tokio::fs::read_to_string(path).await?;
+    let jd = &mut serde_json::Deserializer::from_str(&text);
+    let value = serde_path_to_error::deserialize(jd)?;
+    Ok(value)
+}
+
+/// Like [`load_json`], but a missing file yields `None`.
+pub async fn load_json_if_exists<T: DeserializeOwned>(
+    path: impl AsRef<Path>,
+) -> eyre::Result<Option<T>> {
+    let path = path.as_ref();
+    if tokio::fs::try_exists(path).await.unwrap_or(false) {
+        load_json(path).await.map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Write `value` to a temporary file and rename it over `path`, so an
+/// interrupted write never leaves a truncated state file behind.
+pub async fn save_json<T: Serialize>(path: impl AsRef<Path>, value: &T) -> eyre::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp_path, serde_json::to_string_pretty(value)?).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::deals::Currency;
+use crate::deals::Deal;
+use crate::models::RedditLink;
+use crate::persist::load_json_if_exists;
+use crate::persist::save_json;
+
+/// How alike two product names must be, from 0 to 1, to count as the same product.
+pub const MATCH_THRESHOLD: f64 = 0.6;
+
+/// The window for [`PriceCheck::window_median`], matching how far back shoppers usually look.
+pub const MEDIAN_WINDOW: Duration = Duration::from_secs(90 * 24 * 3600);
+
+/// Words that say nothing about which product a listing is for.
+const NOISE_WORDS: &[&str] = &[
+    "a",
+    "amd",
+    "and",
+    "at",
+    "available",
+    "back",
+    "cpu",
+    "for",
+    "gpu",
+    "in",
+    "intel",
+    "new",
+    "now",
+    "nvidia",
+    "on",
+    "only",
+    "processor",
+    "refurb",
+    "refurbished",
+    "sale",
+    "stock",
+    "the",
+    "w",
+    "with",
+];
+
+/// Prices seen for each product across posts, keyed by normalized product name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PriceHistory {
+    pub products: BTreeMap<String, ProductHistory>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProductHistory {
+    /// Product names as they appeared in post titles.
+    pub names: BTreeSet<String>,
+    /// Prices in the order they were posted.
+    pub points: Vec<PricePoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PricePoint {
+    /// When the post was made, in unix seconds.
+    pub posted_utc: f64,
+    pub price: f64,
+    pub currency: Currency,
+    pub retailer: Option<String>,
+    /// Fullname of the post the price came from.
+    pub link_name: String,
+}
+
+/// How a new price compares to what came before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceCheck {
+    pub product_key: String,
+    pub price: f64,
+    /// The lowest earlier price, if the product was seen before.
+    pub previous_lowest: Option<f64>,
+    /// Median of earlier prices within [`MEDIAN_WINDOW`].
+    pub window_median: Option<f64>,
+}
+
+impl PriceCheck {
+    /// Strictly below every earlier price. False for a product's first sighting.
+    pub fn is_lowest_ever(&self) -> bool {
+        self.previous_lowest.is_some_and(|low| self.price < low)
+    }
+
+    /// How far below the window median the price is, in percent; negative if above.
+    pub fn percent_below_median(&self) -> Option<f64> {
+        let median = self.window_median?;
+        (median > 0.0).then(|| (median - self.price) / median * 100.0)
+    }
+}
+
+impl ProductHistory {
+    pub fn lowest(&self, currency: Currency) -> Option<&PricePoint> {
+        self.points
+            .iter()
+            .filter(|p| p.currency == currency)
+            .min_by(|a, b| a.price.total_cmp(&b.price))
+    }
+
+    /// The lowest price posted before `before_utc`.
+    pub fn lowest_before(&self, currency: Currency, before_utc: f64) -> Option<&PricePoint> {
+        self.points
+            .iter()
+            .filter(|p| p.currency == currency && p.posted_utc < before_utc)
+            .min_by(|a, b| a.price.total_cmp(&b.price))
+    }
+
+    /// Median price of points posted in `from_utc..to_utc`.
+    pub fn median(&self, currency: Currency, from_utc: f64, to_utc: f64) -> Option<f64> {
+        let mut prices: Vec<f64> = self
+            .points
+            .iter()
+            .filter(|p| p.currency == currency && p.posted_utc >= from_utc && p.posted_utc < to_utc)
+            .map(|p| p.price)
+            .collect();
+        if prices.is_empty() {
+            return None;
+        }
+        prices.sort_by(f64::total_cmp);
+        let mid = prices.len() / 2;
+        Some(if prices.len().is_multiple_of(2) {
+            (prices[mid - 1] + prices[mid]) / 2.0
+        } else {
+            prices[mid]
+        })
+    }
+}
+
+impl PriceHistory {
+    pub async fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        Ok(load_json_if_exists(path).await?.unwrap_or_default())
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        save_json(path, self).await
+    }
+
+    /// Record the price from a parsed deal, comparing it against the product's history first.
+    ///
+    /// Returns `None` if the deal has no price or product name. Recording the
+    /// same post again updates its price rather than adding another.
+    pub fn record(&mut self, link: &RedditLink, deal: &Deal) -> Option<PriceCheck> {
+        let price = deal.price?;
+        let key = match self.find(&deal.product) {
+            Some((key, _)) => key.to_string(),
+            None => normalize(&deal.product)?,
+        };
+        let history = self.products.entry(key.clone()).or_default();
+        history.points.retain(|p| p.link_name != link.name);
+
+        let window = MEDIAN_WINDOW.as_secs_f64();
+        let check = PriceCheck {
+            product_key: key,
+            price,
+            // Only earlier posts, so backfilled posts aren't compared to later prices
+            previous_lowest: history
+                .lowest_before(deal.currency, link.created_utc)
+                .map(|p| p.price),
+            window_median: history.median(
+                deal.currency,
+                link.created_utc - window,
+                link.created_utc,
+            ),
+        };
+
+        history.names.insert(deal.product.clone());
+        let at = history
+            .points
+            .partition_point(|p| p.posted_utc <= link.created_utc);
+        history.points.insert(
+            at,
+            PricePoint {
+                posted_utc: link.created_utc,
+                price,
+                currency: deal.currency,
+                retailer: deal.retailer.clone(),
+                link_name: link.name.clone(),
+            },
+        );
+        Some(check)
+    }
+
+    /// The history of the product best matching `product`, if any is close enough.
+    pub fn history(&self, product: &str) -> Option<&ProductHistory> {
+        let (key, _) = self.find(product)?;
+        self.products.get(key)
+    }
+
+    /// The key of the known product best matching `product`, with its similarity.
+    pub fn find(&self, product: &str) -> Option<(&str, f64)> {
+        let query = ProductTokens::of(product);
+        self.products
+            .iter()
+            .filter_map(|(key, history)| {
+                let score = history
+                    .names
+                    .iter()
+                    .map(|name| query.similarity(&ProductTokens::of(name)))
+                    .fold(0.0, f64::max);
+                (score >= MATCH_THRESHOLD).then_some((key.as_str(), score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// A product name as lowercase words, with noise words dropped. `None` if nothing is left.
+pub fn normalize(product: &str) -> Option<String> {
+    let tokens = ProductTokens::of(product);
+    (!tokens.words.is_empty()).then(|| tokens.words.join(" "))
+}
+
+struct ProductTokens {
+    words: Vec<String>,
+    /// Tokens that look like model numbers, e.g. `9800x3d`, `b580` or `7700`.
+    models: BTreeSet<String>,
+}
+
+impl ProductTokens {
+    fn of(product: &str) -> Self {
+        let lower = product.to_lowercase();
+        let words: Vec<String> = lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty() && !NOISE_WORDS.contains(w))
+            .map(str::to_string)
+            .collect();
+        let models = words
+            .iter()
+            .filter(|w| is_model_number(w))
+            .cloned()
+            .collect();
+        Self { words, models }
+    }
+
+    /// Listings with the same model numbers match; differing ones never do.
+    /// Otherwise it's the overlap of their words.
+    fn similarity(&self, other: &Self) -> f64 {
+        let a: BTreeSet<&String> = self.words.iter().collect();
+        let b: BTreeSet<&String> = other.words.iter().collect();
+        let union = a.union(&b).count();
+        let jaccard = if union == 0 {
+            0.0
+        } else {
+            a.intersection(&b).count() as f64 / union as f64
+        };
+        match (self.models.is_empty(), other.models.is_empty()) {
+            (false, false) if self.models == other.models => 0.6 + 0.4 * jaccard,
+            (false, false) => 0.0,
+            _ => jaccard,
+        }
+    }
+}
+
+fn is_model_number(word: &str) -> bool {
+    const UNITS: &[&str] = &["gb", "tb", "mb", "mhz", "ghz", "hz", "mm", "w", "p", "k"];
+    const PREFIXES: &[&str] = &["ddr", "cl", "pcie", "gen", "usb", "wifi"];
+    let has_digit = word.chars().any(|c| c.is_ascii_digit());
+    if !has_digit || word.len() < 3 {
+        return false;
+    }
+    let digits_end = word
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(word.len());
+    if digits_end == word.len() {
+        // Bare numbers like `7700`, but not quantities like `32`
+        return word.len() >= 4;
+    }
+    let is_quantity = digits_end > 0 && UNITS.contains(&&word[digits_end..]);
+    let is_spec = PREFIXES.iter().any(|p| {
+        word.strip_prefix(p)
+            .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit()))
+    });
+    !is_quantity && !is_spec
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn groups_listings_and_flags_drops() -> eyre::Result<()> {
+        let mut links = example_links()?;
+        links.sort_by(|a, b| a.created_utc.total_cmp(&b.created_utc));
+
+        let mut history = PriceHistory::default();
+        for link in &links {
+            if let Some(deal) = Deal::from_link(link) {
+                history.record(link, &deal);
+            }
+        }
+
+        // "AMD R7 9800X3D", "9800X3D available ..." and friends are one product
+        let x3d = history.history("Ryzen 7 9800X3D").unwrap();
+        assert!(x3d.points.len() >= 3);
+        assert!(x3d
+            .names
+            .iter()
+            .all(|n| n.to_lowercase().contains("9800x3d")));
+        assert_eq!(x3d.lowest(Currency::Cad).unwrap().price, 689.0);
+
+        // A different model number is a different product
+        let (key, _) = history.find("AMD Ryzen 5 9600X").unwrap();
+        assert!(!key.contains("9800x3d"));
+        assert!(history.find("Ryzen 9 9950X3D").is_none());
+
+        // A cheaper later post is the lowest ever and below the median
+        let original = links
+            .iter()
+            .position(|l| l.title.starts_with("[CPU] AMD R7 9800X3D"))
+            .unwrap();
+        let mut cheaper = links.swap_remove(original);
+        cheaper.name = "t3_cheaper".to_string();
+        cheaper.created_utc += 3600.0;
+        let mut deal = Deal::from_link(&cheaper).unwrap();
+        deal.price = Some(620.0);
+        let check = history.record(&cheaper, &deal).unwrap();
+        assert!(check.is_lowest_ever());
+        assert!(check.percent_below_median().unwrap() > 5.0);
+
+        // Recording the same post again replaces its point, so it isn't compared to itself
+        let before = history.history("9800X3D").unwrap().points.len();
+        let again = history.record(&cheaper, &deal).unwrap();
+        assert_eq!(again, check);
+        assert_eq!(history.history("9800X3D").unwrap().points.len(), before);
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_order_posts() -> eyre::Result<()> {
+        let links = example_links()?;
+        let post = |name: &str, created_utc: f64| {
+            let mut link = links
+                .iter()
+                .find(|link| link.title.starts_with("[CPU] AMD R7 9800X3D"))
+                .map(serde_json::to_value)
+                .unwrap()
+                .and_then(serde_json::from_value::<RedditLink>)
+                .unwrap();
+            link.name = name.to_string();
+            link.created_utc = created_utc;
+            link
+        };
+        let deal_at = |link: &RedditLink, price: f64| {
+            let mut deal = Deal::from_link(link).unwrap();
+            deal.price = Some(price);
+            deal
+        };
+
+        // The later, cheaper post arrives first
+        let mut history = PriceHistory::default();
+        let later = post("t3_later", 2_000_000.0);
+        history.record(&later, &deal_at(&later, 600.0));
+        let earlier = post("t3_earlier", 1_000_000.0);
+        let check = history.record(&earlier, &deal_at(&earlier, 700.0)).unwrap();
+        // The first sighting, though a cheaper price came later
+        assert_eq!(check.previous_lowest, None);
+
+        // Between them, it's compared against the earlier post only
+        let middle = post("t3_middle", 1_500_000.0);
+        let check = history.record(&middle, &deal_at(&middle, 650.0)).unwrap();
+        assert_eq!(check.previous_lowest, Some(700.0));
+        assert!(check.is_lowest_ever());
+        Ok(())
+    }
+
+    #[test]
+    fn model_numbers() {
+        for word in ["9800x3d", "b580", "7700", "x670e", "3000d"] {
+            assert!(is_model_number(word), "{word}");
+        }
+        for word in ["12gb", "6000mhz", "ddr5", "cl30", "r7", "32", "rx"] {
+            assert!(!is_model_number(word), "{word}");
+        }
+    }
+}
@@ -1,11 +1,61 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::LazyLock;
 use std::time::Duration;
+use std::time::Instant;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 
 static GLOBAL_RATE_LIMITER: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+static LAST_STATUS: std::sync::Mutex<Option<RateLimitStatus>> = std::sync::Mutex::new(None);
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Refuse every network request, so only cached responses are used.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Reddit's view of our request budget, from the `x-ratelimit-*` response headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitStatus {
+    pub used: f64,
+    pub remaining: f64,
+    /// Time until the budget resets, as of `observed_at`.
+    pub reset: Duration,
+    pub observed_at: Instant,
+}
+
+impl RateLimitStatus {
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let get =
+            |name: &str| -> Option<f64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+        Some(Self {
+            used: get("x-ratelimit-used").unwrap_or(0.0),
+            remaining: get("x-ratelimit-remaining")?,
+            reset: Duration::from_secs_f64(get("x-ratelimit-reset")?.max(0.0)),
+            observed_at: Instant::now(),
+        })
+    }
+
+    /// Time left until the budget resets, counted from now.
+    pub fn reset_in(&self) -> Duration {
+        self.reset.saturating_sub(self.observed_at.elapsed())
+    }
+}
+
+/// The rate limit headers from the most recent response, if Reddit sent any.
+pub fn last_rate_limit_status() -> Option<RateLimitStatus> {
+    *LAST_STATUS.lock().unwrap_or_else(|e| e.into_inner())
+}
 
 pub async fn rate_limited_fetch(client: &reqwest::Client, url: &str) -> eyre::Result<String> {
+    if is_offline() {
+        eyre::bail!("Offline, not fetching {url}");
+    }
     {
         // Lock the mutex (ensures only one request at a time can proceed).
         // Once locked, we do the sleep; then we do the actual fetch.
@@ -16,6 +66,9 @@ pub async fn rate_limited_fetch(client: &reqwest::Client, url: &str) -> eyre::Re
     }
 
     let response = client.get(url).send().await?;
+    if let Some(status) = RateLimitStatus::from_headers(response.headers()) {
+        *LAST_STATUS.lock().unwrap_or_else(|e| e.into_inner()) = Some(status);
+    }
     let response = response.error_for_status()?;
     let text = response.text().await?;
     Ok(text)
@@ -0,0 +1,345 @@
+use std::path::Path;
+
+use serde::Serialize;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::QueryParser;
+use tantivy::schema::Field;
+use tantivy::schema::IndexRecordOption;
+use tantivy::schema::Schema;
+use tantivy::schema::TextFieldIndexing;
+use tantivy::schema::TextOptions;
+use tantivy::schema::Value;
+use tantivy::schema::FAST;
+use tantivy::schema::INDEXED;
+use tantivy::schema::STORED;
+use tantivy::schema::STRING;
+use tantivy::schema::TEXT;
+use tantivy::tokenizer::LowerCaser;
+use tantivy::tokenizer::RawTokenizer;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::DateTime;
+use tantivy::Index;
+use tantivy::IndexReader;
+use tantivy::IndexWriter;
+use tantivy::ReloadPolicy;
+use tantivy::TantivyDocument;
+use tantivy::Term;
+
+use crate::comment_tree::CommentTree;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+use crate::storage::Archive;
+
+/// Memory the index writer may use before flushing a segment.
+const WRITER_HEAP_BYTES: usize = 50_000_000;
+
+/// Whole-value, case-insensitive matching for names like subreddits and authors.
+const KEYWORD_TOKENIZER: &str = "keyword";
+
+/// A full-text index over post titles and text and comment bodies.
+///
+/// Queries use tantivy's syntax. Bare words search `title` and `body` and must
+/// all match; other fields are `kind` (`link` or `comment`), `subreddit`,
+/// `author`, `flair` and `created`, e.g.
+/// `"3080 ti" subreddit:bapcsalescanada created:[2025-01-01T00:00:00Z TO 2025-02-01T00:00:00Z]`.
+///
+/// Documents are keyed by fullname, so indexing something again replaces it.
+/// Changes are visible to [`SearchIndex::search`] after [`SearchIndex::commit`].
+pub struct SearchIndex {
+    index: Index,
+    writer: IndexWriter,
+    reader: IndexReader,
+    fields: Fields,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    name: Field,
+    kind: Field,
+    link_id: Field,
+    subreddit: Field,
+    author: Field,
+    flair: Field,
+    title: Field,
+    body: Field,
+    created: Field,
+}
+
+/// A matching post or comment, best match first.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub score: f32,
+    /// Fullname of the post or comment.
+    pub name: String,
+    pub kind: HitKind,
+    /// Fullname of the post, for comments and posts alike.
+    pub link_id: String,
+    pub subreddit: String,
+    pub author: String,
+    /// The post's title; empty for comments.
+    pub title: String,
+    pub created_utc: i64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HitKind {
+    Link,
+    Comment,
+}
+
+impl HitKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            HitKind::Link => "link",
+            HitKind::Comment => "comment",
+        }
+    }
+}
+
+impl SearchIndex {
+    /// Open the index in `dir`, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> eyre::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        let (schema, fields) = schema();
+        let index = Index::open_or_create(MmapDirectory::open(dir.as_ref())?, schema)?;
+        Self::init(index, fields)
+    }
+
+    pub fn open_in_ram() -> eyre::Result<Self> {
+        let (schema, fields) = schema();
+        Self::init(Index::create_in_ram(schema), fields)
+    }
+
+    fn init(index: Index, fields: Fields) -> eyre::Result<Self> {
+        index.tokenizers().register(
+            KEYWORD_TOKENIZER,
+            TextAnalyzer::builder(RawTokenizer::default())
+                .filter(LowerCaser)
+                .build(),
+        );
+        let writer = index.writer(WRITER_HEAP_BYTES)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(Self {
+            index,
+            writer,
+            reader,
+            fields,
+        })
+    }
+
+    pub fn add_link(&mut self, link: &RedditLink) -> eyre::Result<()> {
+        let f = self.fields;
+        let mut doc = TantivyDocument::default();
+        doc.add_text(f.name, &link.name);
+        doc.add_text(f.kind, HitKind::Link.as_str());
+        doc.add_text(f.link_id, &link.name);
+        doc.add_text(f.subreddit, &link.subreddit);
+        doc.add_text(f.author, &link.author);
+        if let Some(flair) = &link.link_flair_text {
+            doc.add_text(f.flair, flair);
+        }
+        doc.add_text(f.title, &link.title);
+        doc.add_text(f.body, &link.selftext);
+        doc.add_date(
+            f.created,
+            DateTime::from_timestamp_secs(link.created_utc as i64),
+        );
+        self.replace(&link.name, doc)
+    }
+
+    /// Index a single comment, not including its replies.
+    pub fn add_comment(&mut self, comment: &RedditComment) -> eyre::Result<()> {
+        let f = self.fields;
+        let mut doc = TantivyDocument::default();
+        doc.add_text(f.name, &comment.name);
+        doc.add_text(f.kind, HitKind::Comment.as_str());
+        doc.add_text(f.link_id, &comment.link_id);
+        doc.add_text(f.subreddit, &comment.subreddit);
+        doc.add_text(f.author, &comment.author);
+        doc.add_text(f.body, &comment.body);
+        doc.add_date(
+            f.created,
+            DateTime::from_timestamp_secs(comment.created_utc as i64),
+        );
+        self.replace(&comment.name, doc)
+    }
+
+    /// Index every comment in the tree, at any depth.
+    pub fn add_comment_tree(&mut self, tree: &CommentTree) -> eyre::Result<usize> {
+        let mut count = 0;
+        for comment in tree {
+            self.add_comment(comment)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn replace(&mut self, name: &str, doc: TantivyDocument) -> eyre::Result<()> {
+        self.writer
+            .delete_term(Term::from_field_text(self.fields.name, name));
+        self.writer.add_document(doc)?;
+        Ok(())
+    }
+
+    /// Make everything added so far searchable and durable.
+    pub fn commit(&mut self) -> eyre::Result<()> {
+        self.commit_with_watermark(self.archive_watermark()?)
+    }
+
+    fn commit_with_watermark(&mut self, watermark: Option<f64>) -> eyre::Result<()> {
+        let mut prepared = self.writer.prepare_commit()?;
+        if let Some(watermark) = watermark {
+            prepared.set_payload(&watermark.to_string());
+        }
+        prepared.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// The archive fetch time indexed up to by [`SearchIndex::update_from_archive`].
+    pub fn archive_watermark(&self) -> eyre::Result<Option<f64>> {
+        let payload = self.index.load_metas()?.payload;
+        Ok(payload.and_then(|p| p.parse().ok()))
+    }
+
+    /// Index whatever the archive stored or updated since the last update, then commit.
+    ///
+    /// Returns how many posts and comments were indexed.
+    pub fn update_from_archive(&mut self, archive: &Archive) -> eyre::Result<usize> {
+        let since = self.archive_watermark()?.unwrap_or(f64::MIN);
+        let mut watermark = since;
+        let mut count = 0;
+        for (fetched_utc, link) in archive.links_fetched_since(since)? {
+            self.add_link(&link)?;
+            watermark = watermark.max(fetched_utc);
+            count += 1;
+        }
+        for (fetched_utc, comment) in archive.comments_fetched_since(since)? {
+            self.add_comment(&comment)?;
+            watermark = watermark.max(fetched_utc);
+            count += 1;
+        }
+        self.commit_with_watermark(Some(watermark))?;
+        Ok(count)
+    }
+
+    /// The `limit` best matches for `query`.
+    pub fn search(&self, query: &str, limit: usize) -> eyre::Result<Vec<SearchHit>> {
+        let f = self.fields;
+        let mut parser = QueryParser::for_index(&self.index, vec![f.title, f.body]);
+        parser.set_conjunction_by_default();
+        let query = parser.parse_query(query)?;
+        let searcher = self.reader.searcher();
+        let top = searcher.search(&query, &TopDocs::with_limit(limit).order_by_score())?;
+        top.into_iter()
+            .map(|(score, address)| {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                let text = |field: Field| {
+                    doc.get_first(field)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                Ok(SearchHit {
+                    score,
+                    name: text(f.name),
+                    kind: match text(f.kind).as_str() {
+                        "link" => HitKind::Link,
+                        _ => HitKind::Comment,
+                    },
+                    link_id: text(f.link_id),
+                    subreddit: text(f.subreddit),
+                    author: text(f.author),
+                    title: text(f.title),
+                    created_utc: doc
+                        .get_first(f.created)
+                        .and_then(|v| v.as_datetime())
+                        .map(|d| d.into_timestamp_secs())
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn schema() -> (Schema, Fields) {
+    let keyword = TextOptions::default().set_stored().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(KEYWORD_TOKENIZER)
+            .set_index_option(IndexRecordOption::Basic),
+    );
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        name: builder.add_text_field("name", STRING | STORED),
+        kind: builder.add_text_field("kind", STRING | STORED),
+        link_id: builder.add_text_field("link_id", STRING | STORED),
+        subreddit: builder.add_text_field("subreddit", keyword.clone()),
+        author: builder.add_text_field("author", keyword),
+        flair: builder.add_text_field("flair", TEXT | STORED),
+        title: builder.add_text_field("title", TEXT | STORED),
+        body: builder.add_text_field("body", TEXT),
+        created: builder.add_date_field("created", INDEXED | STORED | FAST),
+    };
+    (builder.build(), fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::parse_link_comments;
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn fielded_queries_and_incremental_updates() -> eyre::Result<()> {
+        let links = example_links()?;
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let tree = CommentTree::from(parse_link_comments(&text)?);
+
+        let mut archive = Archive::open_in_memory()?;
+        archive.upsert_links(&links)?;
+        let mut index = SearchIndex::open_in_ram()?;
+        assert_eq!(index.update_from_archive(&archive)?, links.len());
+        assert!(index.archive_watermark()?.is_some());
+
+        let link = &links[1];
+        let word = link
+            .title
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|w| w.len() > 3)
+            .unwrap();
+        let query = format!("{word} subreddit:{}", link.subreddit.to_uppercase());
+        let hits = index.search(&query, 50)?;
+        assert!(hits.iter().any(|h| h.name == link.name));
+        assert!(hits.iter().all(|h| h.kind == HitKind::Link));
+
+        let query = format!("author:{} kind:link", link.author);
+        let hits = index.search(&query, 50)?;
+        assert!(hits.iter().all(|h| h.author == link.author));
+
+        // Only the newly archived comments are indexed the second time round
+        archive.upsert_comment_tree(&tree)?;
+        let indexed = index.update_from_archive(&archive)?;
+        assert!(indexed >= tree.len() && indexed < tree.len() + links.len());
+        let comment = tree.iter().find(|c| c.body.len() > 20).unwrap();
+        let word = comment
+            .body
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|w| w.len() > 3)
+            .unwrap();
+        let hits = index.search(&format!("{word} kind:comment"), 200)?;
+        assert!(hits.iter().any(|h| h.name == comment.name));
+
+        // Re-indexing replaces rather than duplicates
+        index.add_link(link)?;
+        index.commit()?;
+        let hits = index.search(&format!("kind:link author:{}", link.author), 50)?;
+        assert_eq!(hits.iter().filter(|h| h.name == link.name).count(), 1);
+        Ok(())
+    }
+}
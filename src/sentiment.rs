@@ -0,0 +1,246 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::comment_tree::CommentTree;
+use crate::models::RedditComment;
+
+/// Phrases about a deal and how much each says for (positive) or against (negative) it.
+const LEXICON: &[(&str, f32)] = &[
+    ("all time low", 2.0),
+    ("atl", 2.0),
+    ("amazing", 1.5),
+    ("awesome", 1.0),
+    ("bought", 1.0),
+    ("bought one", 1.0),
+    ("cheapest", 1.5),
+    ("good deal", 2.0),
+    ("good price", 1.5),
+    ("grabbed", 1.0),
+    ("great deal", 2.0),
+    ("great price", 2.0),
+    ("lowest", 1.5),
+    ("no brainer", 2.0),
+    ("no-brainer", 2.0),
+    ("ordered", 1.0),
+    ("picked one up", 1.0),
+    ("recommend", 1.0),
+    ("reliable", 0.5),
+    ("solid deal", 2.0),
+    ("steal", 2.0),
+    ("thanks", 0.5),
+    ("thank you", 0.5),
+    ("worth it", 1.5),
+    ("avoid it", -2.0),
+    ("avoid this", -2.0),
+    ("bad deal", -2.0),
+    ("cheaper at", -1.5),
+    ("cheaper last", -2.0),
+    ("defective", -1.5),
+    ("doa", -2.0),
+    ("inflated", -1.5),
+    ("meh", -1.0),
+    ("normal price", -1.5),
+    ("not a deal", -2.5),
+    ("not reputable", -2.0),
+    ("not worth", -2.0),
+    ("overpriced", -2.0),
+    ("regular price", -1.5),
+    ("risky", -1.0),
+    ("is a scam", -2.5),
+    ("scammed", -2.5),
+    ("sketchy", -1.5),
+    ("stay away", -2.0),
+    ("terrible", -1.5),
+    ("too risky", -1.5),
+    ("was cheaper", -2.0),
+    ("yikes", -1.0),
+];
+
+/// Lexicon phrases that only say someone bought one. "Haven't ordered" is
+/// no opinion either way, so these are dropped when negated rather than flipped.
+const PURCHASES: &[&str] = &[
+    "bought",
+    "bought one",
+    "grabbed",
+    "ordered",
+    "picked one up",
+];
+
+/// A comment's sentiment is kept within this many points either way, so one
+/// gushing comment can't outweigh the rest of the thread.
+const MAX_SENTIMENT: f32 = 3.0;
+
+/// Fewer comments with an opinion than this and the verdict is [`Verdict::Unclear`].
+const MIN_OPINIONS: usize = 2;
+
+static PHRASES: LazyLock<Regex> = LazyLock::new(|| {
+    let mut phrases: Vec<&str> = LEXICON.iter().map(|(phrase, _)| *phrase).collect();
+    // Longest first so "not worth" wins over "worth it"
+    phrases.sort_by_key(|p| std::cmp::Reverse(p.len()));
+    let alternation: Vec<String> = phrases.iter().map(|p| regex::escape(p)).collect();
+    Regex::new(&format!(r"(?i)\b({})\b", alternation.join("|"))).unwrap()
+});
+
+/// A negation just before a phrase, e.g. "not a" in "not a great deal".
+static NEGATED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(not|never|isn'?t|wasn'?t|no)\s+(\w+\s+)?$").unwrap());
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sentiment {
+    /// Above zero for comments in favour of the deal.
+    pub score: f32,
+    /// The phrases that matched, as written.
+    pub matched: Vec<String>,
+}
+
+/// Score a comment body against the lexicon. Negated praise counts against.
+pub fn score_text(text: &str) -> Sentiment {
+    let mut score = 0.0;
+    let mut matched = Vec::new();
+    for m in PHRASES.find_iter(text) {
+        let phrase = m.as_str().to_lowercase();
+        let Some(&(_, mut weight)) = LEXICON.iter().find(|(p, _)| *p == phrase) else {
+            continue;
+        };
+        if weight > 0.0 && NEGATED.is_match(&text[..m.start()]) {
+            if PURCHASES.contains(&phrase.as_str()) {
+                continue;
+            }
+            weight = -weight;
+        }
+        score += weight;
+        matched.push(m.as_str().to_string());
+    }
+    Sentiment {
+        score: score.clamp(-MAX_SENTIMENT, MAX_SENTIMENT),
+        matched,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Positive,
+    Mixed,
+    Negative,
+    /// Too few comments with an opinion to say.
+    Unclear,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredComment<'a> {
+    pub comment: &'a RedditComment,
+    pub sentiment: Sentiment,
+    /// How much the comment counts, from its score and depth.
+    pub weight: f32,
+}
+
+impl ScoredComment<'_> {
+    pub fn weighted(&self) -> f32 {
+        self.sentiment.score * self.weight
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommunityVerdict<'a> {
+    pub verdict: Verdict,
+    /// Weighted mean sentiment of the comments with an opinion.
+    pub score: f32,
+    /// How many comments had an opinion.
+    pub opinions: usize,
+    /// The comments that count most in favour, strongest first.
+    pub supporting: Vec<ScoredComment<'a>>,
+    /// The comments that count most against, strongest first.
+    pub dissenting: Vec<ScoredComment<'a>>,
+}
+
+/// How much a comment counts: more for upvoted ones, less for deep replies.
+pub fn comment_weight(comment: &RedditComment) -> f32 {
+    let votes = 1.0 + (comment.score.max(0) as f32).ln_1p();
+    let depth = 1.0 + 0.5 * comment.depth.max(0) as f32;
+    let downvoted = if comment.score < 0 { 0.25 } else { 1.0 };
+    votes / depth * downvoted
+}
+
+/// Weigh up every comment in the thread, keeping the `top` strongest on each side.
+pub fn community_verdict(tree: &CommentTree, top: usize) -> CommunityVerdict<'_> {
+    let mut scored: Vec<ScoredComment> = tree
+        .iter()
+        .map(|comment| ScoredComment {
+            comment,
+            sentiment: score_text(&comment.body),
+            weight: comment_weight(comment),
+        })
+        .filter(|s| s.sentiment.score != 0.0)
+        .collect();
+    let opinions = scored.len();
+    let total_weight: f32 = scored.iter().map(|s| s.weight).sum();
+    let score = if total_weight > 0.0 {
+        scored.iter().map(ScoredComment::weighted).sum::<f32>() / total_weight
+    } else {
+        0.0
+    };
+    let verdict = match score {
+        _ if opinions < MIN_OPINIONS => Verdict::Unclear,
+        s if s >= 0.5 => Verdict::Positive,
+        s if s <= -0.5 => Verdict::Negative,
+        _ => Verdict::Mixed,
+    };
+
+    scored.sort_by(|a, b| b.weighted().total_cmp(&a.weighted()));
+    let dissenting: Vec<ScoredComment> = scored
+        .iter()
+        .rev()
+        .take_while(|s| s.weighted() < 0.0)
+        .take(top)
+        .cloned()
+        .collect();
+    let supporting = scored
+        .into_iter()
+        .take_while(|s| s.weighted() > 0.0)
+        .take(top)
+        .collect();
+    CommunityVerdict {
+        verdict,
+        score,
+        opinions,
+        supporting,
+        dissenting,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::parse_link_comments;
+
+    use super::*;
+
+    #[test]
+    fn scores_phrases() {
+        assert!(score_text("Great price, thanks OP!").score > 0.0);
+        assert!(score_text("Not a great price honestly").score < 0.0);
+        assert!(score_text("It was cheaper last week").score < 0.0);
+        assert_eq!(score_text("Not worth it").matched, vec!["Not worth"]);
+        assert_eq!(score_text("For those that have not ordered").score, 0.0);
+        assert_eq!(score_text("How long did delivery take?").score, 0.0);
+    }
+
+    #[test]
+    fn example_thread_verdict() -> eyre::Result<()> {
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let tree = CommentTree::from(parse_link_comments(&text)?);
+        let verdict = community_verdict(&tree, 3);
+        assert!(verdict.opinions >= MIN_OPINIONS);
+        assert_ne!(verdict.verdict, Verdict::Unclear);
+        assert!(verdict.supporting.len() <= 3);
+        assert!(verdict.supporting.iter().all(|s| s.sentiment.score > 0.0));
+        assert!(verdict
+            .dissenting
+            .iter()
+            .any(|s| s.comment.body.starts_with("Yikes")));
+        Ok(())
+    }
+}
@@ -0,0 +1,493 @@
+use std::path::Path;
+
+use rusqlite::params;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::comment_tree::CommentTree;
+use crate::models::now_utc;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS subreddits (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    subreddit_type TEXT NOT NULL,
+    subscribers INTEGER NOT NULL,
+    updated_utc REAL NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS links (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    subreddit TEXT NOT NULL,
+    subreddit_id TEXT NOT NULL,
+    author TEXT NOT NULL,
+    title TEXT NOT NULL,
+    selftext TEXT NOT NULL,
+    url TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    permalink TEXT NOT NULL,
+    link_flair_text TEXT,
+    score INTEGER NOT NULL,
+    upvote_ratio REAL NOT NULL,
+    num_comments INTEGER NOT NULL,
+    over_18 INTEGER NOT NULL,
+    removed_by_category TEXT,
+    created_utc REAL NOT NULL,
+    edited_utc REAL,
+    fetched_utc REAL NOT NULL,
+    raw_json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS links_subreddit_created ON links (subreddit, created_utc);
+CREATE INDEX IF NOT EXISTS links_flair_created ON links (link_flair_text, created_utc);
+CREATE INDEX IF NOT EXISTS links_author ON links (author);
+
+CREATE TABLE IF NOT EXISTS comments (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    link_id TEXT NOT NULL,
+    parent_id TEXT NOT NULL,
+    subreddit TEXT NOT NULL,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    score INTEGER NOT NULL,
+    depth INTEGER NOT NULL,
+    created_utc REAL NOT NULL,
+    edited_utc REAL,
+    fetched_utc REAL NOT NULL,
+    raw_json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS comments_link ON comments (link_id);
+CREATE INDEX IF NOT EXISTS comments_parent ON comments (parent_id);
+CREATE INDEX IF NOT EXISTS comments_author_created ON comments (author, created_utc);
+";
+
+/// A SQLite archive of posts, comments and the subreddits they came from.
+///
+/// The columns hold the fields we query on; the full object is kept alongside
+/// as JSON. [`Archive::upsert_listing`] and [`Archive::upsert_link_comments`]
+/// keep each object as Reddit sent it, fields this crate doesn't model
+/// included, while the `upsert_*` methods taking parsed values can only store
+/// what [`RedditLink`] and [`RedditComment`] hold. Comments are stored without
+/// their `replies`, since each reply is stored as its own row with a `parent_id`.
+///
+/// Calls block on disk I/O, so use `tokio::task::spawn_blocking` from async code.
+pub struct Archive {
+    conn: Connection,
+}
+
+impl Archive {
+    pub fn open(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> eyre::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> eyre::Result<Self> {
+        conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Insert or update a post and the subreddit it was posted in.
+    pub fn upsert_link(&self, link: &RedditLink) -> eyre::Result<()> {
+        upsert_link(&self.conn, link, &serde_json::to_string(link)?)
+    }
+
+    pub fn upsert_links(&mut self, links: &[RedditLink]) -> eyre::Result<()> {
+        let tx = self.conn.transaction()?;
+        for link in links {
+            upsert_link(&tx, link, &serde_json::to_string(link)?)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Insert or update every post in a listing response, keeping each as sent.
+    pub fn upsert_listing(&mut self, response_text: &str) -> eyre::Result<usize> {
+        let listing: RawListing = serde_json::from_str(response_text)?;
+        let tx = self.conn.transaction()?;
+        let count = upsert_raw_links(&tx, &listing)?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Insert or update a single comment, not including its replies.
+    pub fn upsert_comment(&self, comment: &RedditComment) -> eyre::Result<()> {
+        upsert_comment(&self.conn, comment, serde_json::to_value(comment)?)
+    }
+
+    /// Insert or update every comment in the tree, at any depth, in one transaction.
+    pub fn upsert_comment_tree(&mut self, tree: &CommentTree) -> eyre::Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut count = 0;
+        for comment in tree {
+            upsert_comment(&tx, comment, serde_json::to_value(comment)?)?;
+            count += 1;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Insert or update a post and all its comments from its `.json` response,
+    /// keeping each as sent. Returns how many comments were stored.
+    pub fn upsert_link_comments(&mut self, response_text: &str) -> eyre::Result<usize> {
+        let (link, comments): (RawListing, RawListing) = serde_json::from_str(response_text)?;
+        let tx = self.conn.transaction()?;
+        upsert_raw_links(&tx, &link)?;
+        let count = upsert_raw_comments(&tx, &comments)?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    pub fn link(&self, id: &str) -> eyre::Result<Option<RedditLink>> {
+        let raw: Option<String> = self
+            .conn
+            .query_row("SELECT raw_json FROM links WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        raw.map(|raw| Ok(serde_json::from_str(&raw)?)).transpose()
+    }
+
+    /// Posts with the given flair created in `from_utc..to_utc`, newest first,
+    /// optionally limited to one subreddit.
+    pub fn links_by_flair(
+        &self,
+        subreddit: Option<&str>,
+        flair: &str,
+        from_utc: f64,
+        to_utc: f64,
+    ) -> eyre::Result<Vec<RedditLink>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT raw_json FROM links
+             WHERE link_flair_text = ?1
+               AND created_utc >= ?2 AND created_utc < ?3
+               AND (?4 IS NULL OR subreddit = ?4)
+             ORDER BY created_utc DESC",
+        )?;
+        let rows = stmt.query_map(params![flair, from_utc, to_utc, subreddit], |row| {
+            row.get::<_, String>(0)
+        })?;
+        rows.map(|raw| Ok(serde_json::from_str(&raw?)?)).collect()
+    }
+
+    /// Every comment by `author`, newest first.
+    pub fn comments_by_author(&self, author: &str) -> eyre::Result<Vec<RedditComment>> {
+        self.query_comments(
+            "SELECT raw_json FROM comments WHERE author = ?1 ORDER BY created_utc DESC",
+            author,
+        )
+    }
+
+    /// Every stored comment on a post, in thread order. `link_id` is the post's fullname.
+    pub fn comments_for_link(&self, link_id: &str) -> eyre::Result<Vec<RedditComment>> {
+        self.query_comments(
+            "SELECT raw_json FROM comments WHERE link_id = ?1 ORDER BY depth, created_utc",
+            link_id,
+        )
+    }
+
+    /// Direct replies to a post or comment, given its fullname.
+    pub fn replies_to(&self, parent_id: &str) -> eyre::Result<Vec<RedditComment>> {
+        self.query_comments(
+            "SELECT raw_json FROM comments WHERE parent_id = ?1 ORDER BY created_utc",
+            parent_id,
+        )
+    }
+
+    /// Posts stored or updated at or after `since_utc`, oldest fetch first, with their fetch time.
+    pub fn links_fetched_since(&self, since_utc: f64) -> eyre::Result<Vec<(f64, RedditLink)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT fetched_utc, raw_json FROM links WHERE fetched_utc >= ?1 ORDER BY fetched_utc",
+        )?;
+        let rows = stmt.query_map([since_utc], |row| {
+            Ok((row.get::<_, f64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| {
+            let (fetched_utc, raw) = row?;
+            Ok((fetched_utc, serde_json::from_str(&raw)?))
+        })
+        .collect()
+    }
+
+    /// Comments stored or updated at or after `since_utc`, oldest fetch first, with their fetch time.
+    pub fn comments_fetched_since(
+        &self,
+        since_utc: f64,
+    ) -> eyre::Result<Vec<(f64, RedditComment)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT fetched_utc, raw_json FROM comments WHERE fetched_utc >= ?1 ORDER BY fetched_utc",
+        )?;
+        let rows = stmt.query_map([since_utc], |row| {
+            Ok((row.get::<_, f64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| {
+            let (fetched_utc, raw) = row?;
+            Ok((fetched_utc, serde_json::from_str(&raw)?))
+        })
+        .collect()
+    }
+
+    fn query_comments(&self, sql: &str, param: &str) -> eyre::Result<Vec<RedditComment>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([param], |row| row.get::<_, String>(0))?;
+        rows.map(|raw| Ok(serde_json::from_str(&raw?)?)).collect()
+    }
+}
+
+/// A listing response with each child's `data` left as the text Reddit sent.
+#[derive(Deserialize)]
+struct RawListing<'a> {
+    #[serde(borrow)]
+    data: RawChildren<'a>,
+}
+
+#[derive(Deserialize)]
+struct RawChildren<'a> {
+    #[serde(borrow)]
+    children: Vec<RawThing<'a>>,
+}
+
+#[derive(Deserialize)]
+struct RawThing<'a> {
+    kind: &'a str,
+    #[serde(borrow)]
+    data: &'a RawValue,
+}
+
+fn upsert_raw_links(conn: &Connection, listing: &RawListing) -> eyre::Result<usize> {
+    let mut count = 0;
+    for thing in listing.data.children.iter().filter(|t| t.kind == "t3") {
+        let link: RedditLink = serde_json::from_str(thing.data.get())?;
+        upsert_link(conn, &link, thing.data.get())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Stores each comment and, depth first, its replies.
+fn upsert_raw_comments(conn: &Connection, listing: &RawListing) -> eyre::Result<usize> {
+    let mut count = 0;
+    for thing in listing.data.children.iter().filter(|t| t.kind == "t1") {
+        let comment: RedditComment = serde_json::from_str(thing.data.get())?;
+        upsert_comment(conn, &comment, serde_json::from_str(thing.data.get())?)?;
+        count += 1;
+        if let Some(replies) = &comment.replies {
+            count += upsert_raw_comments(conn, &serde_json::from_str(replies.raw().get())?)?;
+        }
+    }
+    Ok(count)
+}
+
+fn upsert_link(conn: &Connection, link: &RedditLink, raw_json: &str) -> eyre::Result<()> {
+    let fetched_utc = now_utc();
+    conn.execute(
+        "INSERT INTO subreddits (id, name, subreddit_type, subscribers, updated_utc)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (id) DO UPDATE SET
+            name = excluded.name,
+            subreddit_type = excluded.subreddit_type,
+            subscribers = excluded.subscribers,
+            updated_utc = excluded.updated_utc",
+        params![
+            link.subreddit_id,
+            link.subreddit,
+            serde_json::to_value(&link.subreddit_type)?
+                .as_str()
+                .unwrap_or_default(),
+            link.subreddit_subscribers,
+            fetched_utc,
+        ],
+    )?;
+    conn.execute(
+        "INSERT INTO links (
+            id, name, subreddit, subreddit_id, author, title, selftext, url, domain,
+            permalink, link_flair_text, score, upvote_ratio, num_comments, over_18,
+            removed_by_category, created_utc, edited_utc, fetched_utc, raw_json
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
+         ON CONFLICT (id) DO UPDATE SET
+            author = excluded.author,
+            title = excluded.title,
+            selftext = excluded.selftext,
+            url = excluded.url,
+            domain = excluded.domain,
+            link_flair_text = excluded.link_flair_text,
+            score = excluded.score,
+            upvote_ratio = excluded.upvote_ratio,
+            num_comments = excluded.num_comments,
+            over_18 = excluded.over_18,
+            removed_by_category = excluded.removed_by_category,
+            edited_utc = excluded.edited_utc,
+            fetched_utc = excluded.fetched_utc,
+            raw_json = excluded.raw_json",
+        params![
+            link.id,
+            link.name,
+            link.subreddit,
+            link.subreddit_id,
+            link.author,
+            link.title,
+            link.selftext,
+            link.url,
+            link.domain,
+            link.permalink,
+            link.link_flair_text,
+            link.score,
+            link.upvote_ratio,
+            link.num_comments,
+            link.over_18,
+            link.removed_by_category,
+            link.created_utc,
+            link.edited,
+            fetched_utc,
+            raw_json,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Comments are stored without `replies`; each reply has its own row.
+fn upsert_comment(
+    conn: &Connection,
+    comment: &RedditComment,
+    mut raw: serde_json::Value,
+) -> eyre::Result<()> {
+    if let Some(replies) = raw.get_mut("replies") {
+        *replies = serde_json::Value::String(String::new());
+    }
+    conn.execute(
+        "INSERT INTO comments (
+            id, name, link_id, parent_id, subreddit, author, body, score, depth,
+            created_utc, edited_utc, fetched_utc, raw_json
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT (id) DO UPDATE SET
+            author = excluded.author,
+            body = excluded.body,
+            score = excluded.score,
+            edited_utc = excluded.edited_utc,
+            fetched_utc = excluded.fetched_utc,
+            raw_json = excluded.raw_json",
+        params![
+            comment.id,
+            comment.name,
+            comment.link_id,
+            comment.parent_id,
+            comment.subreddit,
+            comment.author,
+            comment.body,
+            comment.score,
+            comment.depth,
+            comment.created_utc,
+            comment.edited,
+            now_utc(),
+            raw.to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::parse_link_comments;
+    use crate::models::RedditResponse;
+    use crate::models::RedditThing;
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn round_trips_and_queries() -> eyre::Result<()> {
+        let mut archive = Archive::open_in_memory()?;
+
+        let links = example_links()?;
+        archive.upsert_links(&links)?;
+        // Upserting again updates in place
+        archive.upsert_links(&links)?;
+
+        let first = &links[0];
+        assert_eq!(archive.link(&first.id)?.as_ref(), Some(first));
+
+        let flair = links
+            .iter()
+            .find_map(|l| l.link_flair_text.clone())
+            .expect("some link has flair");
+        let expected = links
+            .iter()
+            .filter(|l| l.link_flair_text.as_ref() == Some(&flair))
+            .count();
+        let flaired = archive.links_by_flair(Some("bapcsalescanada"), &flair, 0.0, f64::MAX)?;
+        assert_eq!(flaired.len(), expected);
+        assert!(archive
+            .links_by_flair(Some("bapcsalescanada"), &flair, 0.0, 1.0)?
+            .is_empty());
+
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?;
+        let tree = CommentTree::from(parse_link_comments(&text)?);
+        let stored = archive.upsert_comment_tree(&tree)?;
+        assert_eq!(stored, tree.len());
+
+        let top = &tree.comments[0];
+        assert!(top.reply_comments()?.count() > 0);
+        let by_author = archive.comments_by_author(&top.author)?;
+        assert!(by_author.iter().any(|c| c.id == top.id));
+        assert!(by_author.iter().all(|c| c.replies.is_none()));
+        assert_eq!(archive.comments_for_link(&top.link_id)?.len(), stored);
+        assert_eq!(
+            archive.replies_to(&top.name)?.len(),
+            top.reply_comments()?.count()
+        );
+        Ok(())
+    }
+
+    fn raw_json(archive: &Archive, table: &str, id: &str) -> eyre::Result<String> {
+        Ok(archive.conn.query_row(
+            &format!("SELECT raw_json FROM {table} WHERE id = ?1"),
+            [id],
+            |row| row.get(0),
+        )?)
+    }
+
+    #[test]
+    fn keeps_responses_as_sent() -> eyre::Result<()> {
+        let mut archive = Archive::open_in_memory()?;
+
+        // Fields the models don't know about survive
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.json")?.replacen(
+            r#""approved_at_utc""#,
+            r#""not_modelled": 1, "approved_at_utc""#,
+            1,
+        );
+        let RedditResponse::Listing(listing) = serde_json::from_str(&text)?;
+        let links = listing.children.len();
+        assert_eq!(archive.upsert_listing(&text)?, links);
+        let Some(RedditThing::Link(first)) = listing.children.first() else {
+            panic!("expected a link first");
+        };
+        assert!(raw_json(&archive, "links", &first.id)?.contains("not_modelled"));
+        assert_eq!(archive.link(&first.id)?.as_ref(), Some(first));
+
+        let text = std::fs::read_to_string("example-payloads/bapcsalescanada.post.json")?.replacen(
+            r#""body""#,
+            r#""not_modelled": 1, "body""#,
+            1,
+        );
+        let tree = CommentTree::from(parse_link_comments(&text)?);
+        assert_eq!(archive.upsert_link_comments(&text)?, tree.len());
+        let nested = tree.iter().find(|c| c.depth > 0).expect("some reply");
+        let raw: serde_json::Value =
+            serde_json::from_str(&raw_json(&archive, "comments", &nested.id)?)?;
+        assert_eq!(raw["replies"], "");
+        assert!(tree
+            .iter()
+            .any(|c| raw_json(&archive, "comments", &c.id)
+                .is_ok_and(|raw| raw.contains("not_modelled"))));
+        Ok(())
+    }
+}
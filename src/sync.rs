@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::client::base_url;
+use crate::client::fetch_info;
+use crate::client::fetch_link_comments;
+use crate::client::fetch_listing;
+use crate::client::refresh_link_comments;
+use crate::client::SubredditSlug;
+use crate::models::RedditLink;
+use crate::models::RedditThing;
+use crate::persist::load_json_if_exists;
+use crate::persist::save_json;
+
+/// What the previous runs have seen, per subreddit.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SyncState {
+    pub subreddits: BTreeMap<String, SubredditSyncState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SubredditSyncState {
+    /// Fullname of the newest non-stickied post seen, e.g. `t3_1iambwd`.
+    pub newest_fullname: Option<String>,
+    pub newest_created_utc: f64,
+    /// Last seen `num_comments` and `created_utc` for each tracked post, keyed by fullname.
+    pub posts: BTreeMap<String, TrackedPost>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TrackedPost {
+    pub num_comments: i64,
+    pub created_utc: f64,
+}
+
+impl SubredditSyncState {
+    pub fn is_seen(&self, link: &RedditLink) -> bool {
+        self.posts.contains_key(&link.name)
+            || self.newest_fullname.as_deref() == Some(link.name.as_str())
+            || (!link.stickied && link.created_utc <= self.newest_created_utc)
+    }
+
+    /// Whether the comment tree needs fetching: the post is new or its comment count moved.
+    pub fn comments_changed(&self, link: &RedditLink) -> bool {
+        self.posts
+            .get(&link.name)
+            .is_none_or(|tracked| tracked.num_comments != link.num_comments)
+    }
+
+    pub fn record(&mut self, link: &RedditLink) {
+        self.posts.insert(
+            link.name.clone(),
+            TrackedPost {
+                num_comments: link.num_comments,
+                created_utc: link.created_utc,
+            },
+        );
+        if !link.stickied && link.created_utc > self.newest_created_utc {
+            self.newest_created_utc = link.created_utc;
+            self.newest_fullname = Some(link.name.clone());
+        }
+    }
+
+    /// Record `link` with a comment count that forces its comments to be fetched next run.
+    pub fn record_for_retry(&mut self, link: &RedditLink) {
+        self.record(link);
+        if let Some(tracked) = self.posts.get_mut(&link.name) {
+            tracked.num_comments = -1;
+        }
+    }
+
+    /// Stop tracking posts created before `cutoff_utc`.
+    pub fn forget_before(&mut self, cutoff_utc: f64) {
+        self.posts.retain(|_, post| post.created_utc >= cutoff_utc);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Posts not seen by any previous run, newest first.
+    pub new_links: Vec<RedditLink>,
+    /// Ids of posts whose comment trees were fetched.
+    pub comments_fetched: Vec<String>,
+    /// Post ids whose comment fetch failed, with the error.
+    pub failures: Vec<(String, String)>,
+}
+
+/// Fetches only what changed in a subreddit since the previous run.
+pub struct IncrementalSync {
+    client: reqwest::Client,
+    state_path: PathBuf,
+    pub state: SyncState,
+    /// Previously seen posts younger than this are rechecked for new comments.
+    pub recheck_secs: f64,
+    /// Upper bound on listing pages walked per run when looking for new posts.
+    pub max_pages: usize,
+}
+
+impl IncrementalSync {
+    pub async fn open(
+        client: reqwest::Client,
+        state_path: impl Into<PathBuf>,
+    ) -> eyre::Result<Self> {
+        let state_path = state_path.into();
+        let state = load_json_if_exists(&state_path).await?.unwrap_or_default();
+        Ok(Self {
+            client,
+            state_path,
+            state,
+            recheck_secs: 7.0 * 24.0 * 60.0 * 60.0,
+            max_pages: 10,
+        })
+    }
+
+    pub fn state_path(&self) -> &Path {
+        &self.state_path
+    }
+
+    /// Fetch new posts and refresh comment trees whose `num_comments` changed,
+    /// then save the updated state.
+    pub async fn sync_subreddit(&mut self, subreddit: &SubredditSlug) -> eyre::Result<SyncReport> {
+        let mut sub_state = self
+            .state
+            .subreddits
+            .get(subreddit.as_ref())
+            .cloned()
+            .unwrap_or_default();
+        let mut report = SyncReport::default();
+
+        // 1) New posts since the newest one we've seen
+        let new_links = self.fetch_new_links(subreddit, &sub_state).await?;
+
+        // 2) Current comment counts for the recent posts we already track
+        let newest = new_links
+            .iter()
+            .map(|l| l.created_utc)
+            .fold(sub_state.newest_created_utc, f64::max);
+        sub_state.forget_before(newest - self.recheck_secs);
+        let tracked: Vec<String> = sub_state.posts.keys().cloned().collect();
+        let rechecked: Vec<RedditLink> = match fetch_info(&self.client, &tracked).await {
+            Ok(things) => things
+                .into_iter()
+                .filter_map(|thing| match thing {
+                    RedditThing::Link(link) => Some(link),
+                    _ => None,
+                })
+                .collect(),
+            Err(e) => {
+                // Keep the new posts so the next run doesn't walk back for them,
+                // with counts that get their comments fetched then
+                new_links
+                    .iter()
+                    .for_each(|link| sub_state.record_for_retry(link));
+                self.save_subreddit(subreddit, sub_state).await?;
+                return Err(e);
+            }
+        };
+
+        // 3) Comment trees for new posts and posts whose count moved
+        for link in new_links.iter().chain(rechecked.iter()) {
+            if !sub_state.comments_changed(link) {
+                continue;
+            }
+            let link_url = format!("{}{}", base_url(), link.permalink);
+            let result = if sub_state.posts.contains_key(&link.name) {
+                refresh_link_comments(&self.client, &link.id, &link_url).await
+            } else {
+                fetch_link_comments(&self.client, &link.id, &link_url).await
+            };
+            match result {
+                Ok(_) => {
+                    sub_state.record(link);
+                    report.comments_fetched.push(link.id.clone());
+                }
+                Err(e) => {
+                    report.failures.push((link.id.clone(), format!("{e:#}")));
+                    // Keep the cursor moving, but with a count that forces a retry next run
+                    sub_state.record_for_retry(link);
+                }
+            }
+        }
+
+        report.new_links = new_links;
+        self.save_subreddit(subreddit, sub_state).await?;
+        Ok(report)
+    }
+
+    async fn save_subreddit(
+        &mut self,
+        subreddit: &SubredditSlug,
+        sub_state: SubredditSyncState,
+    ) -> eyre::Result<()> {
+        self.state
+            .subreddits
+            .insert(subreddit.to_string(), sub_state);
+        save_json(&self.state_path, &self.state).await
+    }
+
+    /// Page through `/new` with the `before` cursor anchored at the newest seen
+    /// post. If that finds nothing (no new posts, or the anchor was deleted),
+    /// fall back to walking down with `after` until a seen post appears.
+    async fn fetch_new_links(
+        &self,
+        subreddit: &SubredditSlug,
+        sub_state: &SubredditSyncState,
+    ) -> eyre::Result<Vec<RedditLink>> {
+        let base = format!(
+            "{}/r/{}/new.json?raw_json=1&limit=100",
+            base_url(),
+            subreddit
+        );
+        let mut new_links = Vec::new();
+
+        if let Some(newest) = &sub_state.newest_fullname {
+            let mut before = newest.clone();
+            for _ in 0..self.max_pages {
+                let listing =
+                    fetch_listing(&self.client, &format!("{base}&before={before}")).await?;
+                let links: Vec<RedditLink> = listing
+                    .children
+                    .into_iter()
+                    .filter_map(|thing| match thing {
+                        RedditThing::Link(link) if !sub_state.is_seen(&link) => Some(link),
+                        _ => None,
+                    })
+                    .collect();
+                if links.is_empty() {
+                    break;
+                }
+                // `before` pages come back newest first, each page newer than the last
+                let page = std::mem::take(&mut new_links);
+                new_links = links;
+                new_links.extend(page);
+                match listing.before {
+                    Some(b) => before = b,
+                    None => break,
+                }
+            }
+            if !new_links.is_empty() {
+                return Ok(new_links);
+            }
+        }
+
+        let mut after: Option<String> = None;
+        for _ in 0..self.max_pages {
+            let url = match &after {
+                Some(a) => format!("{base}&after={a}"),
+                None => base.clone(),
+            };
+            let listing = fetch_listing(&self.client, &url).await?;
+            let mut reached_seen = false;
+            for thing in listing.children {
+                if let RedditThing::Link(link) = thing {
+                    if sub_state.is_seen(&link) {
+                        reached_seen |= !link.stickied;
+                    } else {
+                        new_links.push(link);
+                    }
+                }
+            }
+            after = listing.after;
+            if reached_seen || after.is_none() {
+                break;
+            }
+        }
+        Ok(new_links)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::example_links;
+
+    use super::*;
+
+    #[test]
+    fn tracks_newest_and_comment_counts() -> eyre::Result<()> {
+        let mut links = example_links()?;
+        links.sort_by(|a, b| a.created_utc.total_cmp(&b.created_utc));
+        let (older, newer) = links.split_at(links.len() / 2);
+
+        let mut state = SubredditSyncState::default();
+        older.iter().for_each(|l| state.record(l));
+
+        assert!(older.iter().all(|l| state.is_seen(l)));
+        assert!(newer
+            .iter()
+            .filter(|l| !l.stickied)
+            .all(|l| !state.is_seen(l)));
+        assert!(older.iter().all(|l| !state.comments_changed(l)));
+
+        let mut bumped = example_links()?
+            .into_iter()
+            .find(|l| l.name == older[0].name)
+            .unwrap();
+        bumped.num_comments += 1;
+        assert!(state.comments_changed(&bumped));
+
+        let retried = newer.iter().find(|l| !l.stickied).unwrap();
+        state.record_for_retry(retried);
+        assert!(state.is_seen(retried));
+        assert!(state.comments_changed(retried));
+        Ok(())
+    }
+}
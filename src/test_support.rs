@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+use std::sync::Once;
+
+use crate::client::cache_dir;
+use crate::client::set_cache_dir;
+use crate::models::RedditLink;
+use crate::models::RedditResponse;
+use crate::models::RedditThing;
+
+/// Every post in `example-payloads/bapcsalescanada.json`, in listing order.
+pub fn example_links() -> eyre::Result<Vec<RedditLink>> {
+    let text = std::fs::read_to_string("example-payloads/bapcsalescanada.json")?;
+    let RedditResponse::Listing(listing) = serde_json::from_str(&text)?;
+    Ok(listing
+        .children
+        .into_iter()
+        .filter_map(|thing| match thing {
+            RedditThing::Link(link) => Some(link),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Point the response cache at a fresh temporary directory and return it.
+///
+/// The cache dir is global, so every test in the run shares this one
+/// directory; tests stay out of each other's way by using distinct file names.
+pub fn temp_cache_dir() -> PathBuf {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("reddit-test-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        set_cache_dir(dir);
+    });
+    cache_dir()
+}
+
+/// A fresh temporary directory for this test run, unique to `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("reddit-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::client::fetch_info;
+use crate::models::now_utc;
+use crate::models::RedditLink;
+use crate::models::RedditThing;
+use crate::persist::load_json_if_exists;
+use crate::persist::save_json;
+
+/// One observation of a post's engagement.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EngagementSample {
+    /// Unix seconds when the sample was taken.
+    pub observed_utc: f64,
+    pub score: i64,
+    pub upvote_ratio: f64,
+    pub num_comments: i64,
+    pub total_awards_received: i64,
+}
+
+impl EngagementSample {
+    pub fn of(link: &RedditLink, observed_utc: f64) -> Self {
+        Self {
+            observed_utc,
+            score: link.score,
+            upvote_ratio: link.upvote_ratio,
+            num_comments: link.num_comments,
+            total_awards_received: link.total_awards_received,
+        }
+    }
+}
+
+/// Samples of a single post over time, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EngagementSeries {
+    pub title: String,
+    pub created_utc: f64,
+    pub samples: Vec<EngagementSample>,
+}
+
+impl EngagementSeries {
+    pub fn new(link: &RedditLink) -> Self {
+        Self {
+            title: link.title.clone(),
+            created_utc: link.created_utc,
+            samples: vec![],
+        }
+    }
+
+    pub fn push(&mut self, sample: EngagementSample) {
+        let at = self
+            .samples
+            .partition_point(|s| s.observed_utc <= sample.observed_utc);
+        self.samples.insert(at, sample);
+    }
+
+    pub fn latest(&self) -> Option<&EngagementSample> {
+        self.samples.last()
+    }
+
+    /// Score `age` after the post was created, interpolated between samples.
+    ///
+    /// Posts start with a score of 1 from the submitter's own upvote. Returns
+    /// `None` if no sample was taken at or after `age`.
+    pub fn score_at(&self, age: Duration) -> Option<f64> {
+        let at = self.created_utc + age.as_secs_f64();
+        let mut prev = (self.created_utc, 1.0);
+        for sample in &self.samples {
+            let point = (sample.observed_utc, sample.score as f64);
+            if point.0 >= at {
+                let span = point.0 - prev.0;
+                if span <= 0.0 {
+                    return Some(point.1);
+                }
+                return Some(prev.1 + (point.1 - prev.1) * (at - prev.0) / span);
+            }
+            prev = point;
+        }
+        None
+    }
+
+    /// Score gained per hour over the first `window` of the post's life.
+    pub fn velocity(&self, window: Duration) -> Option<f64> {
+        let hours = window.as_secs_f64() / 3600.0;
+        if hours <= 0.0 {
+            return None;
+        }
+        Some((self.score_at(window)? - 1.0) / hours)
+    }
+
+    /// Score gained per hour in the first hour, for ranking how fast a deal takes off.
+    pub fn first_hour_velocity(&self) -> Option<f64> {
+        self.velocity(Duration::from_secs(3600))
+    }
+}
+
+/// Re-fetches watched posts and records their engagement over time.
+pub struct EngagementTracker {
+    client: reqwest::Client,
+    path: PathBuf,
+    /// Series keyed by post fullname.
+    pub series: BTreeMap<String, EngagementSeries>,
+}
+
+impl EngagementTracker {
+    pub async fn open(client: reqwest::Client, path: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let path = path.into();
+        let series = load_json_if_exists(&path).await?.unwrap_or_default();
+        Ok(Self {
+            client,
+            path,
+            series,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Start tracking `link`, recording its current numbers as the first sample.
+    pub fn watch(&mut self, link: &RedditLink) {
+        self.series
+            .entry(link.name.clone())
+            .or_insert_with(|| EngagementSeries::new(link))
+            .push(EngagementSample::of(link, now_utc()));
+    }
+
+    pub fn unwatch(&mut self, name: &str) -> Option<EngagementSeries> {
+        self.series.remove(name)
+    }
+
+    /// Fetch every watched post through `/api/info` and record a sample, then save.
+    ///
+    /// Returns how many posts were sampled; posts Reddit no longer returns are skipped.
+    pub async fn sample(&mut self) -> eyre::Result<usize> {
+        let names: Vec<String> = self.series.keys().cloned().collect();
+        let observed_utc = now_utc();
+        let mut sampled = 0;
+        for thing in fetch_info(&self.client, &names).await? {
+            if let RedditThing::Link(link) = thing {
+                if let Some(series) = self.series.get_mut(&link.name) {
+                    series.push(EngagementSample::of(&link, observed_utc));
+                    sampled += 1;
+                }
+            }
+        }
+        self.save().await?;
+        Ok(sampled)
+    }
+
+    /// Sample every `interval`, forever. Failed samples are logged and retried next tick.
+    pub async fn run(&mut self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.sample().await {
+                warn!("Sampling tracked posts failed: {e:#}");
+            }
+        }
+    }
+
+    /// Posts ordered by score gained per hour over their first `window`, fastest first.
+    ///
+    /// Posts not yet sampled past `window` are left out.
+    pub fn ranked_by_velocity(&self, window: Duration) -> Vec<(&str, f64)> {
+        let mut ranked: Vec<(&str, f64)> = self
+            .series
+            .iter()
+            .filter_map(|(name, series)| Some((name.as_str(), series.velocity(window)?)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    }
+
+    pub async fn save(&self) -> eyre::Result<()> {
+        save_json(&self.path, &self.series).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(minutes: f64, score: i64) -> EngagementSample {
+        EngagementSample {
+            observed_utc: 1000.0 + minutes * 60.0,
+            score,
+            upvote_ratio: 0.9,
+            num_comments: 0,
+            total_awards_received: 0,
+        }
+    }
+
+    #[test]
+    fn velocity_interpolates_between_samples() {
+        let mut series = EngagementSeries {
+            title: "[GPU] Something ($1)".to_string(),
+            created_utc: 1000.0,
+            samples: vec![],
+        };
+        assert_eq!(series.first_hour_velocity(), None);
+
+        series.push(sample(90.0, 61));
+        series.push(sample(30.0, 21));
+        assert_eq!(series.samples[0].score, 21);
+        assert_eq!(series.score_at(Duration::from_secs(15 * 60)), Some(11.0));
+        assert_eq!(series.score_at(Duration::from_secs(60 * 60)), Some(41.0));
+        assert_eq!(series.first_hour_velocity(), Some(40.0));
+        assert_eq!(series.velocity(Duration::from_secs(2 * 3600)), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures::stream;
+use futures::Stream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::client::base_url;
+use crate::client::fetch_listing;
+use crate::client::SubredditSlug;
+use crate::models::RedditComment;
+use crate::models::RedditLink;
+use crate::models::RedditThing;
+use crate::rate_limit::last_rate_limit_status;
+
+/// Requests made by one poll: `/new` and `/comments`.
+const REQUESTS_PER_POLL: f64 = 2.0;
+
+#[derive(Debug, PartialEq)]
+pub enum WatchEvent {
+    NewPost(Box<RedditLink>),
+    NewComment(Box<RedditComment>),
+}
+
+/// Polls a subreddit's newest posts and comments, emitting each one once.
+pub struct Watcher {
+    client: reqwest::Client,
+    subreddit: SubredditSlug,
+    seen: SeenSet,
+    interval: AdaptiveInterval,
+    /// Emit everything on the first poll instead of only what appears afterwards.
+    pub emit_initial: bool,
+    polled: bool,
+}
+
+impl Watcher {
+    pub fn new(client: reqwest::Client, subreddit: SubredditSlug) -> Self {
+        Self {
+            client,
+            subreddit,
+            seen: SeenSet::new(5000),
+            interval: AdaptiveInterval::new(Duration::from_secs(10), Duration::from_secs(120)),
+            emit_initial: false,
+            polled: false,
+        }
+    }
+
+    /// Poll no faster than `min` and no slower than `max`.
+    pub fn with_interval(mut self, min: Duration, max: Duration) -> Self {
+        self.interval = AdaptiveInterval::new(min, max);
+        self
+    }
+
+    /// Fetch `/new` and `/comments` once, returning the things not seen before.
+    ///
+    /// Both listings are fetched before anything is marked seen, so a failed
+    /// fetch leaves everything to be emitted by the next poll.
+    pub async fn poll(&mut self) -> eyre::Result<Vec<WatchEvent>> {
+        let mut listings = Vec::with_capacity(2);
+        for path in ["new", "comments"] {
+            let url = format!(
+                "{}/r/{}/{}.json?raw_json=1&limit=100",
+                base_url(),
+                self.subreddit,
+                path
+            );
+            listings.push(fetch_listing(&self.client, &url).await?);
+        }
+        let mut events = Vec::new();
+        for listing in listings {
+            // Listings are newest first; emit oldest first
+            for thing in listing.children.into_iter().rev() {
+                let event = match thing {
+                    RedditThing::Link(link) if self.seen.insert(&link.name) => {
+                        WatchEvent::NewPost(Box::new(link))
+                    }
+                    RedditThing::Comment(comment) if self.seen.insert(&comment.name) => {
+                        WatchEvent::NewComment(Box::new(comment))
+                    }
+                    _ => continue,
+                };
+                events.push(event);
+            }
+        }
+        if !self.polled && !self.emit_initial {
+            events.clear();
+        }
+        self.polled = true;
+        Ok(events)
+    }
+
+    /// Poll until the receiving side goes away, sleeping between polls for an
+    /// interval that shrinks while the subreddit is busy, grows while it is
+    /// quiet, and stretches to fit Reddit's remaining rate limit budget.
+    pub async fn run(mut self, tx: mpsc::Sender<WatchEvent>) {
+        loop {
+            match self.poll().await {
+                Ok(events) => {
+                    self.interval.observe(events.len());
+                    for event in events {
+                        if tx.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Polling r/{} failed: {e:#}", self.subreddit);
+                    self.interval.back_off();
+                }
+            }
+            let budget = last_rate_limit_status()
+                .filter(|status| status.remaining > 0.0)
+                .map(|status| {
+                    status
+                        .reset_in()
+                        .mul_f64(REQUESTS_PER_POLL / status.remaining)
+                });
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval.next_delay(budget)) => {}
+                _ = tx.closed() => return,
+            }
+        }
+    }
+
+    /// Run on a background task, delivering events over a channel.
+    pub fn spawn(self, buffer: usize) -> (JoinHandle<()>, mpsc::Receiver<WatchEvent>) {
+        let (tx, rx) = mpsc::channel(buffer.max(1));
+        (tokio::spawn(self.run(tx)), rx)
+    }
+
+    /// Run on a background task, delivering events as a [`Stream`].
+    ///
+    /// The task stops once the stream is dropped.
+    pub fn into_stream(self, buffer: usize) -> impl Stream<Item = WatchEvent> {
+        let (_handle, rx) = self.spawn(buffer);
+        stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|event| (event, rx))
+        })
+    }
+}
+
+/// Remembers the most recent `capacity` fullnames.
+#[derive(Debug)]
+struct SeenSet {
+    capacity: usize,
+    set: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenSet {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            set: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if `name` had not been seen.
+    fn insert(&mut self, name: &str) -> bool {
+        if self.set.contains(name) {
+            return false;
+        }
+        self.set.insert(name.to_string());
+        self.order.push_back(name.to_string());
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug)]
+struct AdaptiveInterval {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl AdaptiveInterval {
+    fn new(min: Duration, max: Duration) -> Self {
+        let max = max.max(min);
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+
+    /// Speed up after a poll that found something, slow down after one that didn't.
+    fn observe(&mut self, new_items: usize) {
+        self.current = if new_items > 0 {
+            self.current / 2
+        } else {
+            self.current.mul_f64(1.5)
+        }
+        .clamp(self.min, self.max);
+    }
+
+    fn back_off(&mut self) {
+        self.current = (self.current * 2).clamp(self.min, self.max);
+    }
+
+    /// The delay before the next poll, no shorter than what `budget` allows per poll.
+    fn next_delay(&self, budget: Option<Duration>) -> Duration {
+        match budget {
+            Some(budget) => self.current.max(budget),
+            None => self.current,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seen_set_forgets_oldest() {
+        let mut seen = SeenSet::new(2);
+        assert!(seen.insert("t3_a"));
+        assert!(!seen.insert("t3_a"));
+        assert!(seen.insert("t3_b"));
+        assert!(seen.insert("t3_c"));
+        assert!(seen.insert("t3_a"));
+        assert!(!seen.insert("t3_c"));
+    }
+
+    #[test]
+    fn interval_follows_activity_and_budget() {
+        let mut interval = AdaptiveInterval::new(Duration::from_secs(10), Duration::from_secs(60));
+        interval.observe(0);
+        assert_eq!(interval.next_delay(None), Duration::from_secs(15));
+        for _ in 0..10 {
+            interval.observe(0);
+        }
+        assert_eq!(interval.next_delay(None), Duration::from_secs(60));
+        interval.observe(3);
+        assert_eq!(interval.next_delay(None), Duration::from_secs(30));
+        assert_eq!(
+            interval.next_delay(Some(Duration::from_secs(45))),
+            Duration::from_secs(45)
+        );
+        interval.back_off();
+        assert_eq!(interval.next_delay(None), Duration::from_secs(60));
+    }
+}